@@ -0,0 +1,340 @@
+use crate::mail::Error;
+
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::Error as _;
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+/// The domains gmailnator's gmail addresses can be received on.
+const GMAIL_DOMAINS:[&str; 2] = ["gmail.com", "googlemail.com"];
+
+/// The domain gmail addresses are canonicalized to.
+const GMAIL_DOMAIN:&str = "gmail.com";
+
+/// The characters allowed in a local part besides letters and digits.
+const LOCAL_PART_SYMBOLS:&str = ".+-_";
+
+/// The kind of address gmailnator generates.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AddressKind {
+
+    /// An address on one of gmailnator's public domains, ex : `tmp1234@gmailnator.com`.
+    PublicDomain,
+
+    /// A gmail address with a `+` alias, ex : `extmp+abcd@gmail.com`. This is the default.
+    #[default]
+    PlusAlias,
+
+    /// A gmail address with dots inserted in its local part, ex : `e.x.tmp@gmail.com`.
+    DotVariant,
+
+}
+
+impl AddressKind {
+
+    /// The value of the `data[]` and `email[]` form fields selecting this kind.
+    pub(crate) fn get_form_value(self) -> &'static str {
+
+        match self {
+
+            AddressKind::PublicDomain   => "1",
+            AddressKind::PlusAlias      => "2",
+            AddressKind::DotVariant     => "3",
+
+        }
+
+    }
+
+    /// Guesses the kind of an existing address from its domain and local part.
+    pub fn of_address(address:&str) -> Self {
+
+        let (local_part, domain) = address.rsplit_once('@').unwrap_or((address, ""));
+
+        if !GMAIL_DOMAINS.iter().any(|gmail_domain| domain.eq_ignore_ascii_case(gmail_domain)) {
+            AddressKind::PublicDomain
+        } else if local_part.contains('+') {
+            AddressKind::PlusAlias
+        } else {
+            AddressKind::DotVariant
+        }
+
+    }
+
+}
+
+/// An e-mail address split in the parts gmailnator relies on, see [`EmailAddress::parse`](#method.parse).
+///
+/// Gmail addresses follow gmail's rules : the dots of the local part and the case don't matter, and `googlemail.com` is `gmail.com`.
+/// Two addresses are equal if their canonical forms are, the `+` tag being kept since it tells aliases apart.
+/// ```
+/// use gmailnator::EmailAddress;
+///
+/// let address = EmailAddress::parse("Ex.Tmp+abcd@googlemail.com").unwrap();
+///
+/// assert_eq!(address.get_local_part(), "Ex.Tmp+abcd");
+/// assert_eq!(address.get_tag(), Some("abcd"));
+/// assert_eq!(address.get_domain(), "googlemail.com");
+/// assert_eq!(address.get_server_id(), "extmp");
+/// assert_eq!(address.get_canonical(), "extmp+abcd@gmail.com");
+///
+/// assert_eq!(address, EmailAddress::parse("extmp+abcd@gmail.com").unwrap());
+/// assert!(address.is_same_mailbox(&EmailAddress::parse("e.x.t.m.p@gmail.com").unwrap()));
+/// ```
+#[derive(Debug, Clone)]
+pub struct EmailAddress {
+
+    address:String,          //As given, without surrounding spaces | Ex : ex.tmp+abcd@gmail.com
+    base_local_part:String,  //Local part without the tag            | Ex : ex.tmp
+    tag:Option<String>,      //Text following the '+'                | Ex : abcd
+    domain:String,           //                                      | Ex : gmail.com
+
+}
+
+impl EmailAddress {
+
+    /// Parses and validates `address`, returns an `Error::InvalidAddressError` explaining what is wrong with it otherwise.
+    pub fn parse(address:&str) -> Result<Self, Error> {
+
+        let address = address.trim();
+
+        let invalid = |reason:&str| Error::InvalidAddressError {address:address.to_string(), reason:reason.to_string()};
+
+        let (local_part, domain) = match address.split_once('@') {
+            Some(parts) => parts,
+            None => { return Err(invalid("missing '@'")); }
+        };
+
+        if domain.contains('@') {
+            return Err(invalid("more than one '@'"));
+        }
+
+        if !local_part.chars().all(|c| c.is_ascii_alphanumeric() || LOCAL_PART_SYMBOLS.contains(c)) {
+            return Err(invalid("forbidden character in the local part"));
+        }
+
+        let (base_local_part, tag) = match local_part.split_once('+') {
+            Some((base, tag)) => (base, Some(tag)),
+            None => (local_part, None),
+        };
+
+        if base_local_part.replace('.', "").is_empty() {
+            return Err(invalid("empty local part"));
+        }
+
+        if tag == Some("") {
+            return Err(invalid("empty '+' tag"));
+        }
+
+        let is_valid_label = |label:&str| !label.is_empty() && !label.starts_with('-') && !label.ends_with('-') && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+
+        if !domain.contains('.') || !domain.split('.').all(is_valid_label) {
+            return Err(invalid("invalid domain"));
+        }
+
+        Ok(Self {
+            address:address.to_string(),
+            base_local_part:base_local_part.to_string(),
+            tag:tag.map(|tag| tag.to_string()),
+            domain:domain.to_string(),
+        })
+
+    }
+
+    /// Returns the address as it was parsed.
+    pub fn as_str(&self) -> &str {
+        &self.address
+    }
+
+    /// Gets the part before the `@`, tag included.
+    pub fn get_local_part(&self) -> &str {
+        &self.address[..self.address.len() - self.domain.len() - 1]
+    }
+
+    /// Gets the text following the `+` of the local part, if any.
+    pub fn get_tag(&self) -> Option<&str> {
+        self.tag.as_deref()
+    }
+
+    /// Gets the part after the `@`.
+    pub fn get_domain(&self) -> &str {
+        &self.domain
+    }
+
+    /// Returns true if the address is received by gmail.
+    pub fn is_gmail(&self) -> bool {
+        GMAIL_DOMAINS.iter().any(|gmail_domain| self.domain.eq_ignore_ascii_case(gmail_domain))
+    }
+
+    /// Returns the identifier of the mailbox on gmailnator's side : the gmail account, or the whole address on a public domain.
+    pub fn get_server_id(&self) -> String {
+
+        if self.is_gmail() {
+            self.base_local_part.replace('.', "").to_lowercase()
+        } else {
+            format!("{}@{}", self.base_local_part, self.domain).to_lowercase()
+        }
+
+    }
+
+    /// The identifier the messages are fetched with : the address as given, cut before the `+` of a tagged alias.
+    pub(crate) fn get_message_server_id(&self) -> &str {
+        self.address.split('+').next().unwrap_or_default()
+    }
+
+    /// Returns the lowercase address, without the dots of the local part and on `gmail.com` for a gmail address.
+    /// The tag is kept, ex : `Ex.Tmp+abcd@googlemail.com` becomes `extmp+abcd@gmail.com`.
+    pub fn get_canonical(&self) -> String {
+
+        if !self.is_gmail() {
+            return self.address.to_lowercase();
+        }
+
+        match &self.tag {
+            Some(tag) => format!("{}+{}@{}", self.get_server_id(), tag.to_lowercase(), GMAIL_DOMAIN),
+            None => format!("{}@{}", self.get_server_id(), GMAIL_DOMAIN),
+        }
+
+    }
+
+    /// Returns the address with its `+` tag replaced by `tag`, only gmail addresses can be tagged.
+    pub fn with_tag(&self, tag:&str) -> Result<EmailAddress, Error> {
+
+        if !self.is_gmail() {
+            return Err(Error::InvalidAddressError {address:self.address.clone(), reason:"only gmail addresses have tagged aliases".to_string()});
+        }
+
+        EmailAddress::parse(&format!("{}+{}@{}", self.base_local_part, tag, self.domain))
+
+    }
+
+    /// Returns the dot variants of the gmail account of the address, see [`AliasIterator`](struct.AliasIterator.html).
+    pub fn aliases(&self) -> AliasIterator {
+
+        let account:Vec<char> = match self.is_gmail() {
+            true => self.get_server_id().chars().collect(),
+            false => Vec::new(),
+        };
+
+        //Each of the gaps between two characters holds a dot or not
+        let variant_count = match account.len() {
+            0 => 0,
+            length => 1_u64.checked_shl(length as u32 - 1).unwrap_or(u64::MAX),
+        };
+
+        AliasIterator {account, variant:0, variant_count, domain_index:0}
+
+    }
+
+    /// Returns true if the mail sent to both addresses ends up in the same mailbox, whatever their tags.
+    pub fn is_same_mailbox(&self, other:&EmailAddress) -> bool {
+        self.is_gmail() == other.is_gmail() && self.get_server_id() == other.get_server_id()
+    }
+
+}
+
+impl PartialEq for EmailAddress {
+
+    fn eq(&self, other:&Self) -> bool {
+        self.get_canonical() == other.get_canonical()
+    }
+
+}
+
+impl Eq for EmailAddress {}
+
+impl Hash for EmailAddress {
+
+    fn hash<H:Hasher>(&self, state:&mut H) {
+        self.get_canonical().hash(state);
+    }
+
+}
+
+impl fmt::Display for EmailAddress {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.address)
+    }
+
+}
+
+impl FromStr for EmailAddress {
+
+    type Err = Error;
+
+    fn from_str(address:&str) -> Result<Self, Self::Err> {
+        EmailAddress::parse(address)
+    }
+
+}
+
+impl Serialize for EmailAddress {
+
+    fn serialize<S:Serializer>(&self, serializer:S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.address)
+    }
+
+}
+
+impl<'de> Deserialize<'de> for EmailAddress {
+
+    fn deserialize<D:Deserializer<'de>>(deserializer:D) -> Result<Self, D::Error> {
+
+        let address = String::deserialize(deserializer)?;
+
+        EmailAddress::parse(&address).map_err(D::Error::custom)
+
+    }
+
+}
+
+/// An `Iterator` over the dot variants of a gmail account, first on `gmail.com` then on `googlemail.com`.
+/// Gmail ignores the dots, so every variant is delivered to the same inbox.
+pub struct AliasIterator {
+
+    account:Vec<char>,
+    variant:u64,          //Bit i set : a dot follows the character i
+    variant_count:u64,
+    domain_index:usize,
+
+}
+
+impl Iterator for AliasIterator {
+
+    type Item = EmailAddress;
+
+    fn next(&mut self) -> Option<Self::Item> {
+
+        if self.variant == self.variant_count {
+
+            self.domain_index += 1;
+            self.variant = 0;
+
+            if self.domain_index >= GMAIL_DOMAINS.len() || self.variant_count == 0 {
+                self.variant = self.variant_count;
+                return None;
+            }
+
+        }
+
+        let mut local_part = String::with_capacity(self.account.len() * 2);
+
+        for (index, character) in self.account.iter().enumerate() {
+
+            local_part.push(*character);
+
+            if index < 64 && self.variant & (1 << index) != 0 {
+                local_part.push('.');
+            }
+
+        }
+
+        self.variant += 1;
+
+        EmailAddress::parse(&format!("{}@{}", local_part, GMAIL_DOMAINS[self.domain_index])).ok()
+
+    }
+
+}
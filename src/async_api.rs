@@ -0,0 +1,477 @@
+use crate::endpoint::*;
+use crate::http::{UrlQuery, Attempt, evaluate_attempt};
+use crate::mail::{GmailnatorInbox, MailMessage, MailSummary, Error};
+use crate::transport::{HttpTransport, HttpResponse};
+use crate::client::GmailnatorClient;
+use crate::session::{Session, CSRF_TOKEN_FIELD};
+use crate::bulk::BulkProgress;
+use crate::address::AddressKind;
+use crate::retry::RetryPolicy;
+
+use futures::channel::oneshot;
+use futures::executor::ThreadPool;
+use futures::future::{self, BoxFuture, FutureExt};
+use futures::stream::{self, BoxStream, StreamExt};
+use futures::task::SpawnExt;
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, Condvar};
+use std::thread;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+
+    static ref TIMER:Arc<Timer> = Timer::start();
+
+}
+
+/// Number of threads of the pool backing the default [`AsyncHttpTransport`](trait.AsyncHttpTransport.html) of a client.
+const DEFAULT_POOL_SIZE:usize = 8;
+
+/// A `Stream` of the messages contained in an inbox, each message is queried when the stream is polled.
+/// The stream ends at the first message that could not be retrieved.
+pub type MailMessageStream = BoxStream<'static, MailMessage>;
+
+/// Like [`MailMessageStream`](type.MailMessageStream.html), but yields the error of every message that could not be retrieved or parsed.
+pub type MailMessageTryStream = BoxStream<'static, Result<MailMessage, Error>>;
+
+/// A `Stream` of the inboxes created by a chunked bulk creation, the async counterpart of [`BulkInboxIterator`](struct.BulkInboxIterator.html).
+pub type BulkInboxStream = BoxStream<'static, Result<GmailnatorInbox, Error>>;
+
+/// The asynchronous counterpart of [`HttpTransport`](trait.HttpTransport.html), used by the `async` methods of the library.
+///
+/// Like its blocking counterpart, http error statuses must be returned as an `Ok` response.
+pub trait AsyncHttpTransport: Send + Sync {
+
+    /// Sends `request` with the url encoded `body` as payload.
+    fn send(&self, request:GmailnatorRequest, body:String) -> BoxFuture<'static, Result<HttpResponse, Error>>;
+
+}
+
+/// An [`AsyncHttpTransport`](trait.AsyncHttpTransport.html) running a blocking [`HttpTransport`](trait.HttpTransport.html) on a fixed-size thread pool.
+///
+/// It does not depend on any particular async runtime, the pool size bounds the number of requests in flight, not the number of inboxes.
+pub struct PooledTransport {
+
+    transport:Arc<dyn HttpTransport>,
+    pool:ThreadPool,
+
+}
+
+impl PooledTransport {
+
+    /// Creates a pooled transport running `transport` on `pool_size` threads.
+    pub fn new(transport:Arc<dyn HttpTransport>, pool_size:usize) -> Self {
+
+        let pool = ThreadPool::builder()
+                              .pool_size(pool_size.max(1))
+                              .name_prefix("gmailnator-")
+                              .create()
+                              .expect("Failed to create the gmailnator thread pool.");
+
+        Self {transport, pool}
+
+    }
+
+}
+
+impl AsyncHttpTransport for PooledTransport {
+
+    fn send(&self, request:GmailnatorRequest, body:String) -> BoxFuture<'static, Result<HttpResponse, Error>> {
+
+        let transport = Arc::clone(&self.transport);
+
+        let task = async move { transport.send(&request, &body) };
+
+        match self.pool.spawn_with_handle(task) {
+
+            Ok(handle) => handle.boxed(),
+            Err(error) => future::ready(Err(Error::ConnectionError(Box::new(error)))).boxed(),
+
+        }
+
+    }
+
+}
+
+/// Async counterpart of `SessionTransport`, sends the session cookies and stores the ones it receives.
+struct SessionAsyncTransport {
+
+    transport:Arc<dyn AsyncHttpTransport>,
+    session:Arc<Session>,
+
+}
+
+impl AsyncHttpTransport for SessionAsyncTransport {
+
+    fn send(&self, mut request:GmailnatorRequest, body:String) -> BoxFuture<'static, Result<HttpResponse, Error>> {
+
+        self.session.apply(&mut request);
+
+        let session = Arc::clone(&self.session);
+        let response = self.transport.send(request, body);
+
+        async move {
+
+            let response = response.await?;
+
+            session.store_cookies(&response);
+
+            Ok(response)
+
+        }.boxed()
+
+    }
+
+}
+
+pub(crate) fn pooled_transport(transport:Arc<dyn HttpTransport>) -> Arc<dyn AsyncHttpTransport> {
+    Arc::new(PooledTransport::new(transport, DEFAULT_POOL_SIZE))
+}
+
+/// Waits for `duration` without blocking the executor, the delays of every request are woken by a single shared timer thread.
+pub(crate) async fn delay(duration:Duration) {
+
+    let _ = TIMER.schedule(Instant::now() + duration).await;
+
+}
+
+#[derive(Default)]
+struct TimerState {
+
+    pending:BTreeMap<(Instant, u64), oneshot::Sender<()>>,   //Keyed by deadline, then by order of scheduling
+    next_id:u64,
+
+}
+
+/// Completes the scheduled delays when they are due, from a thread started on first use.
+#[derive(Default)]
+struct Timer {
+
+    state:Mutex<TimerState>,
+    changed:Condvar,
+
+}
+
+impl Timer {
+
+    fn start() -> Arc<Self> {
+
+        let timer = Arc::new(Self::default());
+        let running = Arc::clone(&timer);
+
+        thread::Builder::new()
+                        .name("gmailnator-timer".to_string())
+                        .spawn(move || running.run())
+                        .expect("Failed to start the gmailnator timer thread.");
+
+        timer
+
+    }
+
+    /// Returns a receiver completed once `deadline` is reached.
+    fn schedule(&self, deadline:Instant) -> oneshot::Receiver<()> {
+
+        let (sender, receiver) = oneshot::channel();
+
+        let mut state = self.state.lock().unwrap();
+
+        let id = state.next_id;
+
+        state.next_id += 1;
+        state.pending.insert((deadline, id), sender);
+
+        self.changed.notify_one();
+
+        receiver
+
+    }
+
+    fn run(&self) {
+
+        let mut state = self.state.lock().unwrap();
+
+        loop {
+
+            let now = Instant::now();
+
+            while let Some(entry) = state.pending.first_entry() {
+
+                if entry.key().0 > now {
+                    break;
+                }
+
+                let _ = entry.remove().send(());
+
+            }
+
+            state = match state.pending.keys().next() {
+
+                Some(&(deadline, _)) => self.changed.wait_timeout(state, deadline - now).unwrap().0,
+                None => self.changed.wait(state).unwrap(),
+
+            };
+
+        }
+
+    }
+
+}
+
+async fn get_response_content_async(transport:&dyn AsyncHttpTransport, request:GmailnatorRequest, query:UrlQuery, policy:&RetryPolicy) -> Result<String, Error> {
+
+    let payload = query.into_query_string();
+
+    let mut attempt:u32 = 0;
+
+    loop {
+
+        attempt += 1;
+
+        match evaluate_attempt(transport.send(request.clone(), payload.clone()).await, policy) {
+
+            Attempt::Done(result) => { return result; }
+            Attempt::Retry(error, retry_after) => match policy.get_delay(attempt, retry_after) {
+
+                Some(duration) => delay(duration).await,
+                None => { return Err(error); }
+
+            }
+
+        }
+
+    }
+
+}
+
+impl GmailnatorClient {
+
+    /// Asynchronously creates a new inbox.
+    pub async fn new_inbox_async(&self) -> Result<GmailnatorInbox, Error> {
+        self.new_inbox_with_kind_async(AddressKind::default()).await
+    }
+
+    /// Asynchronously creates a new inbox whose address is of the given [`AddressKind`](enum.AddressKind.html).
+    pub async fn new_inbox_with_kind_async(&self, kind:AddressKind) -> Result<GmailnatorInbox, Error> {
+
+        let mail_query = GmailnatorInbox::get_generate_email_query(kind);
+
+        let response_str = self.send_async(GmailnatorEndpoint::GetEmail, mail_query).await?;
+
+        GmailnatorInbox::from_generated(&response_str, self.clone(), kind)
+
+    }
+
+    /// Asynchronously creates the desired amount of inbox.
+    /// The `count` argument must be between 1 and 1000 included.
+    pub async fn new_bulk_async(&self, count:u32) -> Result<Vec<GmailnatorInbox>, Error> {
+        self.new_bulk_with_kind_async(count, AddressKind::default()).await
+    }
+
+    /// Asynchronously creates the desired amount of inbox whose addresses are of the given [`AddressKind`](enum.AddressKind.html).
+    /// The `count` argument must be between 1 and 1000 included.
+    pub async fn new_bulk_with_kind_async(&self, count:u32, kind:AddressKind) -> Result<Vec<GmailnatorInbox>, Error> {
+
+        let bulk_query = GmailnatorInbox::get_bulk_query(count, kind)?;
+
+        let response_str = self.send_async(GmailnatorEndpoint::GetEmailBulk, bulk_query).await?;
+
+        let inbox_list = GmailnatorInbox::get_bulk_addresses_from_html(&response_str)
+                                         .iter()
+                                         .filter_map(|address| GmailnatorInbox::with_client(address, self.clone()).ok())
+                                         .map(|inbox| inbox.with_kind(kind))
+                                         .collect();
+
+        Ok(inbox_list)
+
+    }
+
+    /// Creates any amount of inbox, in as many requests of at most 1000 addresses as needed.
+    /// Like [`new_bulk_iter`](#method.new_bulk_iter), the inboxes are yielded as they are received and a failed chunk ends the stream with an `Error::BulkCreationError`.
+    pub fn new_bulk_stream(&self, count:u32) -> Result<BulkInboxStream, Error> {
+        self.new_bulk_stream_with_kind(count, AddressKind::default())
+    }
+
+    /// Like [`new_bulk_stream`](#method.new_bulk_stream), for addresses of the given [`AddressKind`](enum.AddressKind.html).
+    pub fn new_bulk_stream_with_kind(&self, count:u32, kind:AddressKind) -> Result<BulkInboxStream, Error> {
+
+        let progress = BulkProgress::new(count, kind)?;
+        let client = self.clone();
+
+        let chunks = stream::unfold(Some(progress), move |progress| {
+
+            let client = client.clone();
+
+            async move {
+
+                let mut progress = progress?;
+
+                let query = match progress.next_query()? {
+                    Ok(query) => query,
+                    Err(error) => { return Some((vec![Err(progress.fail(error))], None)); }
+                };
+
+                let chunk = match client.send_async(GmailnatorEndpoint::GetEmailBulk, query).await {
+                    Ok(response) => progress.accept(&response),
+                    Err(error) => Err(error),
+                };
+
+                match chunk {
+
+                    Ok(addresses) => {
+
+                        let inboxes = progress.to_inboxes(&addresses, &client).into_iter().map(Ok).collect();
+
+                        Some((inboxes, Some(progress)))
+
+                    },
+                    Err(error) => Some((vec![Err(progress.fail(error))], None)),
+
+                }
+
+            }
+
+        });
+
+        Ok(chunks.flat_map(stream::iter).boxed())
+
+    }
+
+    /// Asynchronously sends the query with the session's csrf token and cookies.
+    /// If the server rejects the token, the session is refreshed and the query sent once more.
+    pub(crate) async fn send_async(&self, ep:GmailnatorEndpoint, query:UrlQuery) -> Result<String, Error> {
+
+        let transport = self.get_async_transport();
+        let session = self.get_session();
+
+        let token = session.ensure_async(transport.as_ref(), self.get_config()).await?;
+
+        match self.send_with_token_async(ep, query.clone(), &token).await {
+
+            Err(Error::ServerError(status)) if Session::is_rejection(status) => {
+
+                session.invalidate(&token);
+
+                let token = session.ensure_async(transport.as_ref(), self.get_config()).await?;
+
+                self.send_with_token_async(ep, query, &token).await
+
+            },
+            result => result,
+
+        }
+
+    }
+
+    async fn send_with_token_async(&self, ep:GmailnatorEndpoint, mut query:UrlQuery, token:&str) -> Result<String, Error> {
+
+        let transport = self.get_async_transport();
+        let session = Arc::clone(self.get_session());
+
+        query.set(CSRF_TOKEN_FIELD, token);
+
+        let request = get_request_from_endpoint(ep, self.get_config());
+
+        let session_transport = SessionAsyncTransport {transport, session};
+
+        get_response_content_async(&session_transport, request, query, &self.get_config().retry_policy).await
+
+    }
+
+}
+
+impl GmailnatorInbox {
+
+    /// Asynchronously creates a new inbox with the default [`GmailnatorClient`](struct.GmailnatorClient.html).
+    /// ```no_run
+    /// # use gmailnator::GmailnatorInbox;
+    /// # futures::executor::block_on(async {
+    /// let inbox = GmailnatorInbox::new_async().await.unwrap();
+    /// # });
+    /// ```
+    pub async fn new_async() -> Result<Self, Error> {
+        GmailnatorClient::default().new_inbox_async().await
+    }
+
+    /// Asynchronously creates the desired amount of inbox with the default [`GmailnatorClient`](struct.GmailnatorClient.html).
+    /// The `count` argument must be between 1 and 1000 included.
+    pub async fn new_bulk_async(count:u32) -> Result<Vec<Self>, Error> {
+        GmailnatorClient::default().new_bulk_async(count).await
+    }
+
+    /// Creates any amount of inbox with the default [`GmailnatorClient`](struct.GmailnatorClient.html), see [`GmailnatorClient::new_bulk_stream`](struct.GmailnatorClient.html#method.new_bulk_stream).
+    pub fn new_bulk_stream(count:u32) -> Result<BulkInboxStream, Error> {
+        GmailnatorClient::default().new_bulk_stream(count)
+    }
+
+    /// Asynchronously returns the received e-mail(s) as a `Stream`.
+    /// Like [`get_messages_iter`](#method.get_messages_iter), the e-mail data is only queried when the stream is polled.
+    /// ```no_run
+    /// use futures::StreamExt;
+    /// # use gmailnator::{GmailnatorInbox, MailMessage};
+    /// # futures::executor::block_on(async {
+    /// # let inbox = GmailnatorInbox::new_async().await.unwrap();
+    /// let messages:Vec<MailMessage> = inbox.get_messages_stream().await.unwrap().collect().await;
+    /// # });
+    /// ```
+    pub async fn get_messages_stream(&self) -> Result<MailMessageStream, Error> {
+
+        let stream = self.get_messages_try_stream()
+                         .await?
+                         .take_while(|message| future::ready(message.is_ok()))
+                         .filter_map(|message| future::ready(message.ok()));
+
+        Ok(stream.boxed())
+
+    }
+
+    /// Asynchronously returns the received e-mail(s) as a `Stream` of `Result`, a message that fails to be retrieved yields its error.
+    pub async fn get_messages_try_stream(&self) -> Result<MailMessageTryStream, Error> {
+
+        let message_summaries = self.get_inbox_message_summaries_async().await?;
+
+        let client = self.client.clone();
+        let temp_server_identifier = self.temp_server.clone();
+
+        let stream = stream::unfold(message_summaries, move |mut message_summaries| {
+
+            let client = client.clone();
+            let temp_server_identifier = temp_server_identifier.clone();
+
+            async move {
+
+                let summary = message_summaries.pop()?;
+
+                let message = GmailnatorInbox::get_message_by_id_async(&client, &temp_server_identifier, &summary).await;
+
+                Some((message, message_summaries))
+
+            }
+
+        });
+
+        Ok(stream.boxed())
+
+    }
+
+    async fn get_message_by_id_async(client:&GmailnatorClient, server_identifier:&str, summary:&MailSummary) -> Result<MailMessage, Error> {
+
+        let get_message_query = GmailnatorInbox::get_message_query(server_identifier, &summary.id);
+
+        let parsable_message = client.send_async(GmailnatorEndpoint::GetMessage, get_message_query).await?;
+
+        Ok(MailMessage::parse(&parsable_message)?.with_summary(summary))
+
+    }
+
+    async fn get_inbox_message_summaries_async(&self) -> Result<Vec<MailSummary>, Error> {
+
+        let query = GmailnatorInbox::get_mailbox_query(self.mail_address.as_str());
+
+        let response_str = self.client.send_async(GmailnatorEndpoint::GetInbox, query).await?;
+
+        Ok(GmailnatorInbox::get_message_summaries_from_response(&response_str))
+
+    }
+
+}
@@ -0,0 +1,487 @@
+//! The `gmailnator` command line tool, built with the `cli` feature : `cargo install gmailnator --features cli`.
+//! The `tui` feature adds the interactive `tui` command.
+
+#[cfg(feature = "tui")]
+mod tui;
+
+use gmailnator::{GmailnatorClient, GmailnatorInbox, MailMessage, AddressKind};
+
+use serde_json::{json, Value};
+
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+const USAGE:&str = "Usage : gmailnator [--json] [--base-url URL] <command>
+
+Commands :
+    new [--kind public|plus|dot]              Creates an inbox and prints its address
+    bulk N [--kind public|plus|dot]           Creates N inboxes
+    list ADDRESS                              Lists the messages of an inbox
+    show ADDRESS ID                           Shows a message as text, with its links and codes
+    watch ADDRESS [--interval SECS] [--all]   Prints the new messages of an inbox as they arrive
+    export ADDRESS [--format mbox|eml|maildir] [--output PATH]
+                                              Exports the messages of an inbox, mbox to the standard output by default
+    tui [ADDRESS...] [--kind public|plus|dot] [--interval SECS]
+                                              Browses inboxes interactively, needs the tui feature
+
+Options :
+    --json                                    Prints json instead of text
+    --base-url URL                            Sends the requests to another server";
+
+const DEFAULT_WATCH_INTERVAL:u64 = 10;
+
+#[derive(PartialEq)]
+enum ExportFormat {
+
+    Mbox,
+    Eml,
+    Maildir,
+
+}
+
+struct Options {
+
+    json:bool,
+    base_url:Option<String>,
+    kind:AddressKind,
+    interval:Duration,
+    all:bool,
+    format:ExportFormat,
+    output:Option<String>,
+
+    arguments:Vec<String>,  //The command followed by its arguments
+
+}
+
+fn parse_options(args:&[String]) -> Result<Options, String> {
+
+    let mut options = Options {
+        json:false,
+        base_url:None,
+        kind:AddressKind::default(),
+        interval:Duration::from_secs(DEFAULT_WATCH_INTERVAL),
+        all:false,
+        format:ExportFormat::Mbox,
+        output:None,
+        arguments:Vec::new(),
+    };
+
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+
+        let mut value = |name:&str| args.next().cloned().ok_or(format!("{} expects a value", name));
+
+        match arg.as_str() {
+
+            "--json" => options.json = true,
+            "--all" => options.all = true,
+            "--base-url" => options.base_url = Some(value(arg)?),
+            "--output" | "-o" => options.output = Some(value(arg)?),
+            "--kind" => {
+                options.kind = match value(arg)?.as_str() {
+                    "public" => AddressKind::PublicDomain,
+                    "plus" => AddressKind::PlusAlias,
+                    "dot" => AddressKind::DotVariant,
+                    kind => { return Err(format!("unknown address kind \"{}\"", kind)); }
+                }
+            },
+            "--format" => {
+                options.format = match value(arg)?.as_str() {
+                    "mbox" => ExportFormat::Mbox,
+                    "eml" => ExportFormat::Eml,
+                    "maildir" => ExportFormat::Maildir,
+                    format => { return Err(format!("unknown export format \"{}\"", format)); }
+                }
+            },
+            "--interval" => {
+                let seconds = value(arg)?;
+                options.interval = match seconds.parse() {
+                    Ok(0) => { return Err("the interval must be at least 1 second".to_string()); },
+                    Ok(seconds) => Duration::from_secs(seconds),
+                    Err(_) => { return Err(format!("invalid interval \"{}\"", seconds)); },
+                };
+            },
+            "--help" | "-h" => { return Err(String::new()); },
+            flag if flag.starts_with("--") => { return Err(format!("unknown option {}", flag)); },
+            _ => options.arguments.push(arg.clone()),
+
+        }
+
+    }
+
+    Ok(options)
+
+}
+
+fn message_to_json(message:&MailMessage) -> Value {
+
+    json!({
+        "id":message.get_id(),
+        "subject":message.get_subject(),
+        "sender_name":message.get_sender_name(),
+        "sender_address":message.get_sender_address(),
+        "recipient":message.get_recipient(),
+        "received":message.get_received_time().map(|received| received.to_rfc3339()),
+    })
+
+}
+
+fn get_sender(message:&MailMessage) -> String {
+
+    match (message.get_sender_name(), message.get_sender_address()) {
+
+        (Some(name), Some(address)) => format!("{} <{}>", name, address),
+        (None, Some(address)) => address.to_string(),
+        (Some(name), None) => name.to_string(),
+        (None, None) => "-".to_string(),
+
+    }
+
+}
+
+fn print_message_line(message:&MailMessage) {
+
+    let received = message.get_received_time().map(|received| received.format("%Y-%m-%d %H:%M").to_string()).unwrap_or_else(|| "-".to_string());
+
+    println!("{:<12} {:<16} {:<40} {}", message.get_id().unwrap_or("-"), received, get_sender(message), message.get_subject());
+
+}
+
+fn print_json(value:&Value) -> Result<(), Box<dyn Error>> {
+
+    println!("{}", serde_json::to_string_pretty(value)?);
+
+    Ok(())
+
+}
+
+fn new_inbox(client:&GmailnatorClient, options:&Options) -> Result<(), Box<dyn Error>> {
+
+    let inbox = client.new_inbox_with_kind(options.kind)?;
+
+    match options.json {
+        true => print_json(&serde_json::to_value(&inbox)?),
+        false => { println!("{}", inbox.get_address()); Ok(()) },
+    }
+
+}
+
+fn bulk(client:&GmailnatorClient, options:&Options, count:&str) -> Result<(), Box<dyn Error>> {
+
+    let count:u32 = count.parse().map_err(|_| format!("invalid count \"{}\"", count))?;
+
+    let mut inboxes = Vec::new();
+
+    for inbox in client.new_bulk_iter(count)?.with_kind(options.kind) {
+
+        let inbox = inbox?;
+
+        match options.json {
+            true => inboxes.push(serde_json::to_value(&inbox)?),
+            false => println!("{}", inbox.get_address()),
+        }
+
+    }
+
+    match options.json {
+        true => print_json(&Value::Array(inboxes)),
+        false => Ok(()),
+    }
+
+}
+
+fn list(inbox:&GmailnatorInbox, options:&Options) -> Result<(), Box<dyn Error>> {
+
+    let mut messages = Vec::new();
+    let mut failures = 0;
+
+    //A message that can't be retrieved doesn't hide the others
+    for result in inbox.get_messages_try_iter()? {
+
+        match result {
+            Ok(message) => messages.push(message),
+            Err(error) => {
+                eprintln!("A message could not be retrieved : {}", error);
+                failures += 1;
+            },
+        }
+
+    }
+
+    if options.json {
+        print_json(&Value::Array(messages.iter().map(message_to_json).collect()))?;
+    } else {
+
+        if messages.is_empty() && failures == 0 {
+            println!("No message in {}", inbox.get_address());
+        }
+
+        for message in &messages {
+            print_message_line(message);
+        }
+
+    }
+
+    match failures {
+        0 => Ok(()),
+        failures => Err(format!("{} of {} messages could not be retrieved", failures, failures + messages.len()).into()),
+    }
+
+}
+
+fn show(inbox:&GmailnatorInbox, options:&Options, id:&str) -> Result<(), Box<dyn Error>> {
+
+    let message = inbox.get_message(id)?;
+
+    let links = message.links();
+    let codes = message.extract_codes();
+
+    if options.json {
+
+        let mut value = message_to_json(&message);
+
+        value["text"] = json!(message.text());
+        value["html"] = json!(message.get_raw_content());
+        value["links"] = json!(links.iter().map(|link| json!({"url":link.get_url(), "text":link.get_text()})).collect::<Vec<Value>>());
+        value["codes"] = json!(codes);
+
+        return print_json(&value);
+
+    }
+
+    println!("From    : {}", get_sender(&message));
+    println!("To      : {}", message.get_recipient().unwrap_or_else(|| inbox.get_address()));
+    println!("Subject : {}", message.get_subject());
+
+    if let Some(received) = message.get_received_time() {
+        println!("Date    : {}", received.to_rfc2822());
+    }
+
+    println!("\n{}", message.text());
+
+    if !links.is_empty() {
+
+        println!("\nLinks :");
+
+        for (index, link) in links.iter().enumerate() {
+            println!("  [{}] {} {}", index + 1, link.get_text(), link.get_url());
+        }
+
+    }
+
+    if !codes.is_empty() {
+        println!("\nCodes : {}", codes.join(", "));
+    }
+
+    Ok(())
+
+}
+
+fn watch(inbox:&GmailnatorInbox, options:&Options) -> Result<(), Box<dyn Error>> {
+
+    let mut watcher = inbox.watch();
+
+    if !options.all {
+        watcher.skip_existing()?;
+    }
+
+    if !options.json {
+        eprintln!("Watching {}, press Ctrl+C to stop", inbox.get_address());
+    }
+
+    loop {
+
+        match watcher.try_poll() {
+
+            Ok(results) => for result in results {
+
+                match (result, options.json) {
+                    (Ok(message), true) => println!("{}", serde_json::to_string(&message_to_json(&message))?),
+                    (Ok(message), false) => print_message_line(&message),
+                    (Err(error), _) => eprintln!("A message could not be retrieved : {}", error),
+                }
+
+            },
+            Err(error) => eprintln!("Poll failed, retrying : {}", error),
+
+        }
+
+        io::stdout().flush()?;
+
+        thread::sleep(options.interval);
+
+    }
+
+}
+
+fn export(inbox:&GmailnatorInbox, options:&Options) -> Result<(), Box<dyn Error>> {
+
+    let count = match (&options.format, &options.output) {
+
+        (ExportFormat::Mbox, Some(path)) => inbox.export_mbox(fs::File::create(path)?)?,
+        (ExportFormat::Mbox, None) => inbox.export_mbox(io::stdout())?,
+        (ExportFormat::Maildir, Some(path)) => inbox.sync_to_maildir(path)?,
+        (ExportFormat::Eml, Some(path)) => {
+
+            fs::create_dir_all(path)?;
+
+            let mut count = 0;
+
+            for message in inbox.get_messages_try_iter()? {
+
+                let message = message?;
+
+                fs::write(Path::new(path).join(format!("{}.eml", message.get_file_key())), message.to_eml())?;
+
+                count += 1;
+
+            }
+
+            count
+
+        },
+        (_, None) => { return Err("the eml and maildir formats need an --output folder".into()); }
+
+    };
+
+    //The mbox itself goes to the standard output
+    if options.output.is_none() {
+        return Ok(());
+    }
+
+    match options.json {
+        true => print_json(&json!({"address":inbox.get_address(), "exported":count, "output":options.output})),
+        false => { println!("Exported {} messages of {}", count, inbox.get_address()); Ok(()) },
+    }
+
+}
+
+fn run(options:&Options) -> Result<(), Box<dyn Error>> {
+
+    let mut builder = GmailnatorClient::builder();
+
+    if let Some(base_url) = &options.base_url {
+        builder = builder.base_url(base_url);
+    }
+
+    let client = builder.build()?;
+
+    let inbox = |address:&str| client.inbox_from_address(address);
+
+    let arguments:Vec<&str> = options.arguments.iter().map(String::as_str).collect();
+
+    match arguments.as_slice() {
+
+        ["new"] => new_inbox(&client, options),
+        ["bulk", count] => bulk(&client, options, count),
+        ["list", address] => list(&inbox(address)?, options),
+        ["show", address, id] => show(&inbox(address)?, options, id),
+        ["watch", address] => watch(&inbox(address)?, options),
+        ["export", address] => export(&inbox(address)?, options),
+        #[cfg(feature = "tui")]
+        ["tui", addresses @ ..] => tui::run(&client, addresses, options.kind, options.interval),
+        _ => Err(USAGE.into()),
+
+    }
+
+}
+
+fn main() {
+
+    let args:Vec<String> = env::args().skip(1).collect();
+
+    let options = match parse_options(&args) {
+
+        Ok(options) => options,
+        Err(error) if error.is_empty() => {
+            println!("{}", USAGE);
+            return;
+        },
+        Err(error) => {
+            eprintln!("error : {}\n\n{}", error, USAGE);
+            process::exit(2);
+        },
+
+    };
+
+    if let Err(error) = run(&options) {
+
+        match options.json {
+            true => eprintln!("{}", json!({"error":error.to_string()})),
+            false => eprintln!("error : {}", error),
+        }
+
+        process::exit(1);
+
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn parse(args:&[&str]) -> Result<Options, String> {
+        parse_options(&args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>())
+    }
+
+    #[test]
+    fn flags_are_accepted_around_the_command() {
+
+        let options = parse(&["--json", "export", "fake@gmail.com", "--format", "eml", "-o", "out", "--base-url", "http://localhost:8080"]).unwrap();
+
+        assert!(options.json);
+        assert!(options.format == ExportFormat::Eml);
+        assert_eq!(options.output.as_deref(), Some("out"));
+        assert_eq!(options.base_url.as_deref(), Some("http://localhost:8080"));
+        assert_eq!(options.arguments, ["export", "fake@gmail.com"]);
+
+        let options = parse(&["watch", "fake@gmail.com", "--all", "--interval", "3"]).unwrap();
+
+        assert!(options.all && !options.json);
+        assert_eq!(options.interval, Duration::from_secs(3));
+        assert_eq!(options.arguments, ["watch", "fake@gmail.com"]);
+
+    }
+
+    #[test]
+    fn defaults_and_kinds() {
+
+        let options = parse(&["new"]).unwrap();
+
+        assert!(options.format == ExportFormat::Mbox);
+        assert_eq!(options.kind, AddressKind::default());
+        assert_eq!(options.interval, Duration::from_secs(DEFAULT_WATCH_INTERVAL));
+        assert!(options.output.is_none() && options.base_url.is_none());
+
+        assert_eq!(parse(&["bulk", "5", "--kind", "dot"]).unwrap().kind, AddressKind::DotVariant);
+        assert_eq!(parse(&["--kind", "plus", "new"]).unwrap().kind, AddressKind::PlusAlias);
+        assert_eq!(parse(&["--kind", "public", "new"]).unwrap().kind, AddressKind::PublicDomain);
+
+    }
+
+    #[test]
+    fn invalid_arguments_are_reported() {
+
+        assert_eq!(parse(&["new", "--kind", "other"]).err().unwrap(), "unknown address kind \"other\"");
+        assert_eq!(parse(&["export", "a", "--format", "pdf"]).err().unwrap(), "unknown export format \"pdf\"");
+        assert_eq!(parse(&["watch", "a", "--interval", "soon"]).err().unwrap(), "invalid interval \"soon\"");
+        assert_eq!(parse(&["watch", "a", "--interval", "0"]).err().unwrap(), "the interval must be at least 1 second");
+        assert_eq!(parse(&["tui", "--interval", "-1"]).err().unwrap(), "invalid interval \"-1\"");
+        assert_eq!(parse(&["list", "a", "--output"]).err().unwrap(), "--output expects a value");
+        assert_eq!(parse(&["list", "--verbose"]).err().unwrap(), "unknown option --verbose");
+
+        //An empty error asks for the usage
+        assert_eq!(parse(&["new", "--help"]).err().unwrap(), "");
+
+    }
+
+}
@@ -0,0 +1,536 @@
+//! The interactive mode of the `gmailnator` binary, built with the `tui` feature.
+
+use gmailnator::{GmailnatorClient, GmailnatorInbox, MailMessage, MailLink, InboxWatcher, AddressKind};
+
+use ratatui::DefaultTerminal;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::Frame;
+
+use chrono::Local;
+
+use std::io::{self, Write};
+use std::sync::mpsc::{self, Sender, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the terminal events are checked for.
+const EVENT_POLL_INTERVAL:Duration = Duration::from_millis(200);
+
+const HELP:&str = "q quit | tab switch pane | ↑↓ move | n new inbox | r refresh | d untrack | y copy address | c copy code | 1-9 copy link";
+
+const BASE64_ALPHABET:&[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Requests sent to the worker, which does every network call so that the interface never freezes.
+enum Command {
+
+    Track(GmailnatorInbox),
+    Untrack(String),
+    Create(AddressKind),
+    Refresh,
+
+}
+
+/// Results sent back by the worker.
+enum Update {
+
+    Created(String),
+    Messages(String, Vec<MailMessage>),
+    Refreshed,
+    Failed(String),
+
+}
+
+fn poll_watcher(watcher:&mut InboxWatcher, updates:&Sender<Update>) {
+
+    let address = watcher.get_inbox().get_address().to_string();
+
+    let results = match watcher.try_poll() {
+
+        Ok(results) => results,
+        Err(error) => {
+            let _ = updates.send(Update::Failed(format!("{} : {}", address, error)));
+            return;
+        },
+
+    };
+
+    let mut messages = Vec::new();
+
+    for result in results {
+
+        match result {
+            Ok(message) => messages.push(message),
+            Err(error) => { let _ = updates.send(Update::Failed(format!("{} : {}", address, error))); },
+        }
+
+    }
+
+    if !messages.is_empty() {
+        let _ = updates.send(Update::Messages(address, messages));
+    }
+
+}
+
+fn run_worker(client:GmailnatorClient, commands:Receiver<Command>, updates:Sender<Update>, interval:Duration) {
+
+    let mut watchers:Vec<InboxWatcher> = Vec::new();
+
+    //The commands don't delay the automatic refresh, which keeps its own deadline
+    let mut next_refresh = Instant::now() + interval;
+
+    loop {
+
+        match commands.recv_timeout(next_refresh.saturating_duration_since(Instant::now())) {
+
+            Ok(Command::Track(inbox)) => {
+
+                let mut watcher = inbox.watch();
+
+                poll_watcher(&mut watcher, &updates);
+                watchers.push(watcher);
+
+            },
+            Ok(Command::Untrack(address)) => watchers.retain(|watcher| watcher.get_inbox().get_address() != address),
+            Ok(Command::Create(kind)) => match client.new_inbox_with_kind(kind) {
+
+                Ok(inbox) => {
+
+                    let _ = updates.send(Update::Created(inbox.get_address().to_string()));
+
+                    watchers.push(inbox.watch());
+
+                },
+                Err(error) => { let _ = updates.send(Update::Failed(format!("Could not create an inbox : {}", error))); },
+
+            },
+            Ok(Command::Refresh) | Err(RecvTimeoutError::Timeout) => {
+
+                for watcher in &mut watchers {
+                    poll_watcher(watcher, &updates);
+                }
+
+                let _ = updates.send(Update::Refreshed);
+
+                next_refresh = Instant::now() + interval;
+
+            },
+            Err(RecvTimeoutError::Disconnected) => { return; }
+
+        }
+
+    }
+
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Pane {
+
+    Inboxes,
+    Messages,
+    Reader,
+
+}
+
+struct TrackedInbox {
+
+    address:String,
+    messages:Vec<MailMessage>,  //Newest first
+
+}
+
+/// The rendered content of the selected message, kept until another message is selected.
+struct ReaderView {
+
+    id:Option<String>,
+    lines:String,
+    links:Vec<MailLink>,
+    codes:Vec<String>,
+
+}
+
+struct App {
+
+    inboxes:Vec<TrackedInbox>,
+    inbox_state:ListState,
+    message_state:ListState,
+    focus:Pane,
+
+    reader:Option<ReaderView>,
+    scroll:u16,
+
+    status:String,
+    kind:AddressKind,
+    commands:Sender<Command>,
+
+}
+
+impl App {
+
+    fn get_inbox(&self) -> Option<&TrackedInbox> {
+        self.inboxes.get(self.inbox_state.selected()?)
+    }
+
+    fn get_message(&self) -> Option<&MailMessage> {
+        self.get_inbox()?.messages.get(self.message_state.selected()?)
+    }
+
+    fn track(&mut self, address:String) {
+
+        if self.inboxes.iter().any(|inbox| inbox.address == address) {
+            return;
+        }
+
+        self.inboxes.push(TrackedInbox {address, messages:Vec::new()});
+
+        if self.inbox_state.selected().is_none() {
+            self.select_inbox(0);
+        }
+
+    }
+
+    fn select_inbox(&mut self, index:usize) {
+
+        self.inbox_state.select(Some(index));
+        self.message_state.select(if self.get_inbox().map(|inbox| inbox.messages.is_empty()) == Some(false) { Some(0) } else { None });
+        self.scroll = 0;
+
+    }
+
+    fn apply(&mut self, update:Update) {
+
+        match update {
+
+            Update::Created(address) => {
+
+                self.status = format!("Created {}", address);
+                self.track(address);
+                self.select_inbox(self.inboxes.len() - 1);
+
+            },
+            Update::Messages(address, messages) => {
+
+                let count = messages.len();
+                let is_selected = self.get_inbox().map(|inbox| inbox.address == address) == Some(true);
+
+                if let Some(inbox) = self.inboxes.iter_mut().find(|inbox| inbox.address == address) {
+
+                    for message in messages {
+                        inbox.messages.insert(0, message);
+                    }
+
+                    //Keep the selected message selected
+                    if is_selected {
+                        self.message_state.select(Some(self.message_state.selected().map(|index| index + count).unwrap_or(0)));
+                    }
+
+                    self.status = format!("{} new message(s) in {}", count, address);
+
+                }
+
+            },
+            Update::Refreshed => self.status = format!("Refreshed at {}", Local::now().format("%H:%M:%S")),
+            Update::Failed(error) => self.status = error,
+
+        }
+
+    }
+
+    fn move_selection(&mut self, down:bool) {
+
+        let step = |selected:Option<usize>, length:usize| match (selected, length) {
+            (_, 0) => None,
+            (None, _) => Some(0),
+            (Some(index), length) if down => Some((index + 1).min(length - 1)),
+            (Some(index), _) => Some(index.saturating_sub(1)),
+        };
+
+        match self.focus {
+
+            Pane::Inboxes => if let Some(index) = step(self.inbox_state.selected(), self.inboxes.len()) { self.select_inbox(index) },
+            Pane::Messages => {
+
+                let length = self.get_inbox().map(|inbox| inbox.messages.len()).unwrap_or(0);
+
+                self.message_state.select(step(self.message_state.selected(), length));
+                self.scroll = 0;
+
+            },
+            Pane::Reader => self.scroll = if down { self.scroll.saturating_add(1) } else { self.scroll.saturating_sub(1) },
+
+        }
+
+    }
+
+    fn update_reader(&mut self) {
+
+        let message = match self.get_message() {
+            Some(message) => message,
+            None => { self.reader = None; return; }
+        };
+
+        if let Some(reader) = &self.reader {
+
+            if reader.id.as_deref() == message.get_id() && reader.id.is_some() {
+                return;
+            }
+
+        }
+
+        let mut lines = format!("From    : {}\nSubject : {}\n", message.get_sender_address().unwrap_or("-"), message.get_subject());
+
+        if let Some(received) = message.get_received_time() {
+            lines.push_str(&format!("Date    : {}\n", received.with_timezone(&Local).format("%Y-%m-%d %H:%M")));
+        }
+
+        lines.push('\n');
+        lines.push_str(&message.text());
+
+        let links = message.links();
+        let codes = message.extract_codes();
+
+        if !links.is_empty() {
+
+            lines.push_str("\n\nLinks :\n");
+
+            for (index, link) in links.iter().enumerate() {
+                lines.push_str(&format!("[{}] {} {}\n", index + 1, link.get_text(), link.get_url()));
+            }
+
+        }
+
+        if !codes.is_empty() {
+            lines.push_str(&format!("\nCodes : {}", codes.join(", ")));
+        }
+
+        self.reader = Some(ReaderView {id:message.get_id().map(str::to_string), lines, links, codes});
+
+    }
+
+    fn copy(&mut self, label:&str, text:Option<String>) {
+
+        self.status = match text {
+
+            Some(text) => match copy_to_clipboard(&text) {
+                Ok(_) => format!("Copied {} : {}", label, text),
+                Err(error) => format!("Could not copy the {} : {}", label, error),
+            },
+            None => format!("No {} to copy", label),
+
+        };
+
+    }
+
+    /// Handles a key press, returns false to quit.
+    fn handle_key(&mut self, code:KeyCode) -> bool {
+
+        let panes = [Pane::Inboxes, Pane::Messages, Pane::Reader];
+        let focused = panes.iter().position(|pane| *pane == self.focus).unwrap_or(0);
+
+        match code {
+
+            KeyCode::Char('q') | KeyCode::Esc => { return false; }
+            KeyCode::Tab | KeyCode::Right => self.focus = panes[(focused + 1) % panes.len()],
+            KeyCode::BackTab | KeyCode::Left => self.focus = panes[(focused + panes.len() - 1) % panes.len()],
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(true),
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(false),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_add(10),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(10),
+            KeyCode::Char('n') => {
+                self.status = "Creating an inbox...".to_string();
+                let _ = self.commands.send(Command::Create(self.kind));
+            },
+            KeyCode::Char('r') => {
+                self.status = "Refreshing...".to_string();
+                let _ = self.commands.send(Command::Refresh);
+            },
+            KeyCode::Char('d') | KeyCode::Delete => if let Some(index) = self.inbox_state.selected() {
+
+                let inbox = self.inboxes.remove(index);
+
+                let _ = self.commands.send(Command::Untrack(inbox.address.clone()));
+
+                self.status = format!("Stopped tracking {}", inbox.address);
+                self.inbox_state.select(None);
+
+                if !self.inboxes.is_empty() {
+                    self.select_inbox(index.min(self.inboxes.len() - 1));
+                }
+
+            },
+            KeyCode::Char('y') => {
+                let address = self.get_inbox().map(|inbox| inbox.address.clone());
+                self.copy("address", address);
+            },
+            KeyCode::Char('c') => {
+                let code = self.reader.as_ref().and_then(|reader| reader.codes.first().cloned());
+                self.copy("code", code);
+            },
+            KeyCode::Char(digit @ '1'..='9') => {
+                let index = digit as usize - '1' as usize;
+                let link = self.reader.as_ref().and_then(|reader| reader.links.get(index)).map(|link| link.get_url().to_string());
+                self.copy("link", link);
+            },
+            _ => {},
+
+        }
+
+        true
+
+    }
+
+    fn draw(&mut self, frame:&mut Frame) {
+
+        self.update_reader();
+
+        let [main, status, help] = Layout::vertical([Constraint::Min(3), Constraint::Length(1), Constraint::Length(1)]).areas(frame.area());
+        let [inboxes, messages, reader] = Layout::horizontal([Constraint::Percentage(25), Constraint::Percentage(35), Constraint::Percentage(40)]).areas(main);
+
+        let highlight = Style::default().add_modifier(Modifier::REVERSED);
+
+        let inbox_items:Vec<ListItem> = self.inboxes.iter()
+                                                    .map(|inbox| ListItem::new(format!("{} ({})", inbox.address, inbox.messages.len())))
+                                                    .collect();
+
+        frame.render_stateful_widget(List::new(inbox_items).block(self.block("Inboxes", Pane::Inboxes)).highlight_style(highlight), inboxes, &mut self.inbox_state);
+
+        let message_items:Vec<ListItem> = self.get_inbox()
+                                              .map(|inbox| inbox.messages.iter().map(|message| {
+
+                                                  let sender = message.get_sender_name().or_else(|| message.get_sender_address()).unwrap_or("-");
+
+                                                  ListItem::new(format!("{} - {}", sender, message.get_subject()))
+
+                                              }).collect())
+                                              .unwrap_or_default();
+
+        frame.render_stateful_widget(List::new(message_items).block(self.block("Messages", Pane::Messages)).highlight_style(highlight), messages, &mut self.message_state);
+
+        self.draw_reader(frame, reader);
+
+        frame.render_widget(Paragraph::new(self.status.as_str()).style(Style::default().fg(Color::Yellow)), status);
+        frame.render_widget(Paragraph::new(HELP).style(Style::default().fg(Color::DarkGray)), help);
+
+    }
+
+    fn draw_reader(&self, frame:&mut Frame, area:Rect) {
+
+        let content = self.reader.as_ref().map(|reader| reader.lines.as_str()).unwrap_or("No message selected");
+
+        let paragraph = Paragraph::new(content).block(self.block("Message", Pane::Reader))
+                                               .wrap(Wrap {trim:false})
+                                               .scroll((self.scroll, 0));
+
+        frame.render_widget(paragraph, area);
+
+    }
+
+    fn block(&self, title:&str, pane:Pane) -> Block<'static> {
+
+        let style = if self.focus == pane { Style::default().fg(Color::Cyan) } else { Style::default() };
+
+        Block::bordered().title(format!(" {} ", title)).border_style(style)
+
+    }
+
+}
+
+/// Copies `text` with the OSC 52 escape sequence, which terminals and terminal multiplexers forward to the system clipboard, even over ssh.
+fn copy_to_clipboard(text:&str) -> io::Result<()> {
+
+    let mut encoded = String::new();
+
+    for chunk in text.as_bytes().chunks(3) {
+
+        let block = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+
+        for index in 0..4 {
+
+            if index <= chunk.len() {
+                encoded.push(BASE64_ALPHABET[(block >> (18 - 6 * index) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+
+        }
+
+    }
+
+    let mut stdout = io::stdout();
+
+    write!(stdout, "\x1b]52;c;{}\x07", encoded)?;
+
+    stdout.flush()
+
+}
+
+fn run_app(terminal:&mut DefaultTerminal, app:&mut App, updates:&Receiver<Update>) -> io::Result<()> {
+
+    loop {
+
+        while let Ok(update) = updates.try_recv() {
+            app.apply(update);
+        }
+
+        terminal.draw(|frame| app.draw(frame))?;
+
+        if event::poll(EVENT_POLL_INTERVAL)? {
+
+            if let Event::Key(key) = event::read()? {
+
+                if key.kind == KeyEventKind::Press && !app.handle_key(key.code) {
+                    return Ok(());
+                }
+
+            }
+
+        }
+
+    }
+
+}
+
+/// Browses the inboxes of `addresses` and the ones created with `n`, refreshing them every `interval`.
+pub fn run(client:&GmailnatorClient, addresses:&[&str], kind:AddressKind, interval:Duration) -> Result<(), Box<dyn std::error::Error>> {
+
+    if interval.is_zero() {
+        return Err("the refresh interval can't be zero".into());
+    }
+
+    let inboxes = addresses.iter().map(|address| client.inbox_from_address(address)).collect::<Result<Vec<GmailnatorInbox>, _>>()?;
+
+    let (commands, worker_commands) = mpsc::channel();
+    let (worker_updates, updates) = mpsc::channel();
+
+    let mut app = App {
+        inboxes:Vec::new(),
+        inbox_state:ListState::default(),
+        message_state:ListState::default(),
+        focus:Pane::Inboxes,
+        reader:None,
+        scroll:0,
+        status:"Press n to create an inbox".to_string(),
+        kind,
+        commands:commands.clone(),
+    };
+
+    for inbox in inboxes {
+        app.track(inbox.get_address().to_string());
+        let _ = commands.send(Command::Track(inbox));
+    }
+
+    let worker_client = client.clone();
+
+    thread::spawn(move || run_worker(worker_client, worker_commands, worker_updates, interval));
+
+    let mut terminal = ratatui::init();
+
+    let result = run_app(&mut terminal, &mut app, &updates);
+
+    ratatui::restore();
+
+    Ok(result?)
+
+}
@@ -0,0 +1,239 @@
+use crate::endpoint::GmailnatorEndpoint;
+use crate::http::UrlQuery;
+use crate::mail::{GmailnatorInbox, Error};
+use crate::client::GmailnatorClient;
+use crate::address::{AddressKind, EmailAddress};
+
+use std::collections::{HashSet, VecDeque};
+
+/// Number of consecutive chunks without any new address after which a bulk creation gives up.
+const MAX_STALLED_CHUNKS:u32 = 3;
+
+/// Keeps track of a bulk creation split in chunks : the addresses already received and the ones still to create.
+pub(crate) struct BulkProgress {
+
+    requested:u32,
+    kind:AddressKind,
+    seen:HashSet<String>,   //Keys of the addresses already created, see get_key
+    stalled_chunks:u32,
+
+}
+
+impl BulkProgress {
+
+    pub fn new(requested:u32, kind:AddressKind) -> Result<Self, Error> {
+
+        if requested < GmailnatorInbox::MIN_BULK_COUNT {
+            return Err(Error::InvalidCountError(requested));
+        }
+
+        Ok(Self {requested, kind, seen:HashSet::new(), stalled_chunks:0})
+
+    }
+
+    pub fn get_created(&self) -> u32 {
+        self.seen.len() as u32
+    }
+
+    pub fn get_requested(&self) -> u32 {
+        self.requested
+    }
+
+    pub fn get_kind(&self) -> AddressKind {
+        self.kind
+    }
+
+    pub fn set_kind(&mut self, kind:AddressKind) {
+        self.kind = kind;
+    }
+
+    /// The key two addresses are duplicates for : their canonical form, or the lowercase address for dot variants.
+    /// Dot variants of a same account are the same address to gmail, but not to the sites they are given to.
+    fn get_key(&self, address:&EmailAddress) -> String {
+
+        match self.kind {
+            AddressKind::DotVariant => address.as_str().to_lowercase(),
+            _ => address.get_canonical(),
+        }
+
+    }
+
+    /// Creates the inboxes of the addresses returned by `accept`.
+    pub fn to_inboxes(&self, addresses:&[String], client:&GmailnatorClient) -> Vec<GmailnatorInbox> {
+
+        addresses.iter()
+                 .filter_map(|address| GmailnatorInbox::with_client(address, client.clone()).ok())
+                 .map(|inbox| inbox.with_kind(self.kind))
+                 .collect()
+
+    }
+
+    /// The query of the next chunk, `None` once every address was created.
+    pub fn next_query(&self) -> Option<Result<UrlQuery, Error>> {
+
+        let remaining = self.requested - self.get_created();
+
+        if remaining == 0 {
+            return None;
+        }
+
+        Some(GmailnatorInbox::get_bulk_query(remaining.min(GmailnatorInbox::MAX_BULK_COUNT), self.kind))
+
+    }
+
+    /// Returns the addresses of a chunk response that were not received before, without exceeding the requested count.
+    pub fn accept(&mut self, response:&str) -> Result<Vec<String>, Error> {
+
+        let mut new_addresses = Vec::new();
+
+        let addresses = GmailnatorInbox::get_bulk_addresses_from_html(response);
+
+        for address in addresses.iter().filter_map(|address| EmailAddress::parse(address).ok()) {
+
+            if self.get_created() == self.requested {
+                break;
+            }
+
+            if self.seen.insert(self.get_key(&address)) {
+                new_addresses.push(address.as_str().to_string());
+            }
+
+        }
+
+        if new_addresses.is_empty() {
+
+            self.stalled_chunks += 1;
+
+            if self.stalled_chunks >= MAX_STALLED_CHUNKS {
+                return Err(Error::HtmlParsingError(response.to_string()));
+            }
+
+        } else {
+            self.stalled_chunks = 0;
+        }
+
+        Ok(new_addresses)
+
+    }
+
+    /// Wraps the error of a chunk with the progress made before it.
+    pub fn fail(&self, error:Error) -> Error {
+
+        Error::BulkCreationError {
+            created:self.get_created(),
+            requested:self.requested,
+            source:Box::new(error),
+        }
+
+    }
+
+}
+
+/// An `Iterator` creating any amount of inboxes, in chunks of at most 1000 addresses.
+///
+/// Inboxes are yielded as soon as their chunk is received and an address is never yielded twice.
+/// If a chunk fails, an `Error::BulkCreationError` holding the number of inboxes created so far is yielded and the iteration stops.
+/// ```no_run
+/// use gmailnator::{GmailnatorClient, AddressKind};
+///
+/// let client = GmailnatorClient::default();
+///
+/// for inbox in client.new_bulk_iter(10_000).unwrap().with_kind(AddressKind::DotVariant) {
+///
+///     match inbox {
+///         Ok(inbox) => println!("{}", inbox.get_address()),
+///         Err(error) => eprintln!("Bulk creation stopped : {}", error),
+///     }
+///
+/// }
+/// ```
+pub struct BulkInboxIterator {
+
+    client:GmailnatorClient,
+    progress:BulkProgress,
+    pending:VecDeque<GmailnatorInbox>,
+    finished:bool,
+
+}
+
+impl BulkInboxIterator {
+
+    pub(crate) fn new(client:&GmailnatorClient, count:u32) -> Result<Self, Error> {
+
+        Ok(Self {
+            client:client.clone(),
+            progress:BulkProgress::new(count, AddressKind::default())?,
+            pending:VecDeque::new(),
+            finished:false,
+        })
+
+    }
+
+    /// Sets the kind of the addresses to create, [`AddressKind::PlusAlias`](enum.AddressKind.html#variant.PlusAlias) by default.
+    /// It applies to the chunks requested after the call.
+    pub fn with_kind(mut self, kind:AddressKind) -> Self {
+        self.progress.set_kind(kind);
+        self
+    }
+
+    /// Returns the kind of the addresses being created.
+    pub fn get_kind(&self) -> AddressKind {
+        self.progress.get_kind()
+    }
+
+    /// Returns the number of inboxes created so far.
+    pub fn get_created(&self) -> u32 {
+        self.progress.get_created()
+    }
+
+    /// Returns the number of inboxes that were asked for.
+    pub fn get_requested(&self) -> u32 {
+        self.progress.get_requested()
+    }
+
+}
+
+impl Iterator for BulkInboxIterator {
+
+    type Item = Result<GmailnatorInbox, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+
+        loop {
+
+            if let Some(inbox) = self.pending.pop_front() {
+                return Some(Ok(inbox));
+            }
+
+            if self.finished {
+                return None;
+            }
+
+            let query = match self.progress.next_query() {
+
+                Some(query) => query,
+                None => {
+                    self.finished = true;
+                    return None;
+                }
+
+            };
+
+            let chunk = query.and_then(|query| self.client.send(GmailnatorEndpoint::GetEmailBulk, query))
+                             .and_then(|response| self.progress.accept(&response));
+
+            match chunk {
+
+                Ok(addresses) => self.pending.extend(self.progress.to_inboxes(&addresses, &self.client)),
+                Err(error) => {
+                    self.finished = true;
+                    return Some(Err(self.progress.fail(error)));
+                }
+
+            }
+
+        }
+
+    }
+
+}
@@ -0,0 +1,163 @@
+use crate::mail::{MailMessage, Error};
+
+use regex::Regex;
+
+/// The default pattern : a number of 4 to 8 digits.
+const DEFAULT_CODE_PATTERN:&str = r"\b\d{4,8}\b";
+
+/// The words verification codes are usually introduced with.
+const DEFAULT_KEYWORDS:[&str; 12] = ["code", "verification", "verify", "otp", "one-time", "one time", "passcode", "pin", "security", "confirm", "login", "sign in"];
+
+/// Characters that join numbers into dates, phone numbers or amounts, ex : `2024-05-01`, `555-1234`, `1.000`.
+const NUMBER_JOINERS:&str = "-/.:,";
+
+lazy_static! {
+
+    static ref DEFAULT_EXTRACTOR:CodeExtractor = CodeExtractor::new();
+
+}
+
+/// Finds the one-time codes of a message, in its subject and its text, see [`MailMessage::extract_codes`](struct.MailMessage.html#method.extract_codes).
+///
+/// The codes are ranked by their distance to the closest keyword, a code without any keyword nearby coming last.
+/// A pattern with a capture group extracts the group instead of the whole match.
+/// ```
+/// use gmailnator::CodeExtractor;
+///
+/// let extractor = CodeExtractor::with_patterns(&[r"\b[A-Z]{3}-\d{3}\b", r"(?i)token : (\w+)"]).unwrap()
+///                                .with_keywords(&["code"]);
+///
+/// assert_eq!(extractor.extract("Order 2024 : your code is ABC-123"), vec!["ABC-123"]);
+/// assert_eq!(extractor.extract("Token : f00ba5"), vec!["f00ba5"]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct CodeExtractor {
+
+    patterns:Vec<Regex>,
+    keywords:Vec<String>,
+
+}
+
+impl CodeExtractor {
+
+    /// Creates an extractor of 4 to 8 digit codes, ranked by their distance to words like "code" or "verification".
+    pub fn new() -> Self {
+
+        Self {
+            patterns:vec![Regex::new(DEFAULT_CODE_PATTERN).unwrap()],
+            keywords:DEFAULT_KEYWORDS.iter().map(|keyword| keyword.to_string()).collect(),
+        }
+
+    }
+
+    /// Creates an extractor of the codes matching any of `patterns`, returns an `Error::InvalidPatternError` if one of them is not a valid regular expression.
+    pub fn with_patterns(patterns:&[&str]) -> Result<Self, Error> {
+
+        let patterns = patterns.iter()
+                               .map(|pattern| Regex::new(pattern).map_err(|error| Error::InvalidPatternError {pattern:pattern.to_string(), reason:error.to_string()}))
+                               .collect::<Result<Vec<Regex>, Error>>()?;
+
+        Ok(Self {patterns, ..Self::new()})
+
+    }
+
+    /// Sets the words the codes are ranked by the distance to, the case is ignored.
+    pub fn with_keywords(mut self, keywords:&[&str]) -> Self {
+        self.keywords = keywords.iter().map(|keyword| keyword.to_lowercase()).collect();
+        self
+    }
+
+    /// Returns the distinct codes of `text`, the closest to a keyword first.
+    pub fn extract(&self, text:&str) -> Vec<String> {
+        self.extract_from(&[text])
+    }
+
+    /// Returns the distinct codes of the subject and the text of `message`, the closest to a keyword first.
+    pub fn extract_from_message(&self, message:&MailMessage) -> Vec<String> {
+        self.extract_from(&[message.get_subject(), &message.text()])
+    }
+
+    fn extract_from(&self, texts:&[&str]) -> Vec<String> {
+
+        let mut candidates:Vec<(usize, usize, String)> = Vec::new(); //(distance to a keyword, order of appearance, code)
+
+        for text in texts {
+
+            let lowercase = text.to_lowercase();
+
+            //Lowercasing may change the length of some characters, the distances are then only approximate
+            let keyword_positions:Vec<(usize, usize)> = self.keywords.iter()
+                                                                     .flat_map(|keyword| lowercase.match_indices(keyword.as_str()).map(|(start, keyword)| (start, start + keyword.len())))
+                                                                     .filter(|&(start, _)| !lowercase[..start].ends_with(char::is_alphanumeric)) //"pin" is not in "shipping"
+                                                                     .collect();
+
+            for pattern in &self.patterns {
+
+                for capture in pattern.captures_iter(text) {
+
+                    let code = match capture.get(1).or_else(|| capture.get(0)) {
+                        Some(code) => code,
+                        None => continue,
+                    };
+
+                    if is_part_of_number(text, code.start(), code.end()) {
+                        continue;
+                    }
+
+                    let distance = keyword_positions.iter()
+                                                    .map(|&(start, end)| if end <= code.start() { code.start() - end } else { start.saturating_sub(code.end()) })
+                                                    .min()
+                                                    .unwrap_or(usize::MAX);
+
+                    candidates.push((distance, candidates.len(), code.as_str().to_string()));
+
+                }
+
+            }
+
+        }
+
+        candidates.sort();
+
+        let mut codes:Vec<String> = Vec::new();
+
+        for (_, _, code) in candidates {
+
+            if !codes.contains(&code) {
+                codes.push(code);
+            }
+
+        }
+
+        codes
+
+    }
+
+}
+
+impl Default for CodeExtractor {
+
+    fn default() -> Self {
+        Self::new()
+    }
+
+}
+
+/// Returns true if the match is a piece of a date, phone number or amount, like `2024` in `2024-05-01`.
+fn is_part_of_number(text:&str, start:usize, end:usize) -> bool {
+
+    let joined = |joiner:Option<char>, neighbour:Option<char>| {
+        matches!((joiner, neighbour), (Some(joiner), Some(neighbour)) if NUMBER_JOINERS.contains(joiner) && neighbour.is_ascii_digit())
+    };
+
+    let mut before = text[..start].chars().rev();
+    let mut after = text[end..].chars();
+
+    joined(before.next(), before.next()) || joined(after.next(), after.next())
+
+}
+
+/// Extracts the codes of a message with the default extractor.
+pub(crate) fn extract_default_codes(message:&MailMessage) -> Vec<String> {
+    DEFAULT_EXTRACTOR.extract_from_message(message)
+}
@@ -1,52 +1,87 @@
-use ureq::Request;
+use crate::client::ClientConfig;
 
 impl GmailnatorEndpoint { //IMPLEMENT EP
 
-    pub fn to_request(self) -> GmailnatorRequest {
+    pub fn to_request(self, base_url:&str) -> GmailnatorRequest {
 
-        match self {
-            
-            GmailnatorEndpoint::GetEmail        => GmailnatorRequest::from(HttpMethod::Post, "https://gmailnator.com/index/indexquery"),
-            GmailnatorEndpoint::GetEmailBulk    => GmailnatorRequest::from(HttpMethod::Post, "https://gmailnator.com/bulk-emails"),
+        let (method, path) = match self {
 
-            GmailnatorEndpoint::GetInbox        => GmailnatorRequest::from(HttpMethod::Post, "https://gmailnator.com/mailbox/mailboxquery"),
-            GmailnatorEndpoint::GetMessage      => GmailnatorRequest::from(HttpMethod::Post, "https://gmailnator.com/mailbox/get_single_message"),
-        
-        }
+            GmailnatorEndpoint::GetEmail        => (HttpMethod::Post, "/index/indexquery"),
+            GmailnatorEndpoint::GetEmailBulk    => (HttpMethod::Post, "/bulk-emails"),
+
+            GmailnatorEndpoint::GetInbox        => (HttpMethod::Post, "/mailbox/mailboxquery"),
+            GmailnatorEndpoint::GetMessage      => (HttpMethod::Post, "/mailbox/get_single_message"),
+
+        };
+
+        GmailnatorRequest::from(method, &format!("{}{}", base_url.trim_end_matches('/'), path))
 
     }
 
 }
 
+/// Describes an http request the library wants to send : its method, url and headers.
+/// The url encoded payload is handed separately to the [`HttpTransport`](trait.HttpTransport.html).
+#[derive(Debug, Clone)]
 pub struct GmailnatorRequest {
 
     method:HttpMethod,
     url:String,
+    headers:Vec<(String, String)>,
 
 }
 
 impl GmailnatorRequest {
 
+    /// Creates a request without any header.
     pub fn from(method:HttpMethod, url:&str) -> Self {
-        Self {method, url:url.to_string()}
+        Self {method, url:url.to_string(), headers:Vec::new()}
     }
 
+    /// Gets the request's http method.
     pub fn get_method(&self) -> HttpMethod {
         self.method
     }
 
+    /// Gets the request's complete url.
     pub fn get_url(&self) -> &str {
         &self.url
     }
 
+    /// Gets every header of the request as `(name, value)` pairs.
+    pub fn get_headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// Gets the value of the first header named `name`, the comparison is case insensitive.
+    pub fn get_header(&self, name:&str) -> Option<&str> {
+
+        self.headers.iter()
+                    .find(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+                    .map(|(_, value)| value.as_str())
+
+    }
+
+    /// Sets a header, replacing any header that has the same name.
+    pub fn set_header(&mut self, name:&str, value:&str) {
+
+        self.headers.retain(|(header_name, _)| !header_name.eq_ignore_ascii_case(name));
+        self.headers.push((name.to_string(), value.to_string()));
+
+    }
+
 }
 
-#[derive(Copy, Clone, PartialEq)]
+/// The http methods used by the library.
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum HttpMethod {
+    /// Http `GET`.
     Get,
+    /// Http `POST`.
     Post,
 }
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Copy, Clone, PartialEq)]
 pub enum GmailnatorEndpoint {
 
@@ -57,31 +92,26 @@ pub enum GmailnatorEndpoint {
 
 }
 
-const EMPTY_CSRF_COOKIE:&'static str = "csrf_gmailnator_cookie=;";
-const DEFAULT_USER_AGENT:&'static str = "Mozilla/5.0 (Windows NT 6.4; WOW64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/41.0.2225.0 Safari/537.36";
-
-const URL_ENCODED_CONTENT_TYPE:&'static str = "application/x-www-form-urlencoded; charset=UTF-8";
+pub const DEFAULT_BASE_URL:&str = "https://gmailnator.com";
+pub const DEFAULT_USER_AGENT:&str = "Mozilla/5.0 (Windows NT 6.4; WOW64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/41.0.2225.0 Safari/537.36";
 
-pub fn get_request_from_endpoint(ep:GmailnatorEndpoint) -> Request {
+const URL_ENCODED_CONTENT_TYPE:&str = "application/x-www-form-urlencoded; charset=UTF-8";
 
-    let request_param = ep.to_request();
+pub fn get_request_from_endpoint(ep:GmailnatorEndpoint, config:&ClientConfig) -> GmailnatorRequest {
 
-    let (url, method) = (request_param.get_url(), request_param.get_method());
+    let mut base_req = ep.to_request(&config.base_url);
 
-    let mut base_req = match method {
+    base_req.set_header("User-Agent", &config.user_agent);
 
-        HttpMethod::Get  => ureq::get(url),
-        HttpMethod::Post => ureq::post(url),
-
-    };
-
-    base_req.set("Cookie", EMPTY_CSRF_COOKIE);
-    base_req.set("User-Agent", DEFAULT_USER_AGENT);
+    if base_req.get_method() == HttpMethod::Post {
+        base_req.set_header("Content-Type", URL_ENCODED_CONTENT_TYPE);
+    }
 
-    if method == HttpMethod::Post {
-        base_req.set("Content-Type", URL_ENCODED_CONTENT_TYPE); 
+    //The custom headers come last so that they replace the default ones
+    for (name, value) in &config.headers {
+        base_req.set_header(name, value);
     }
 
     base_req
 
-}
\ No newline at end of file
+}
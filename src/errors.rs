@@ -1,5 +1,11 @@
 use std::error::Error;
 use std::fmt;
+use std::io;
+use std::time::Duration;
+
+/// The underlying error of a transport failure.
+/// With the default transport, it is the `std::io::Error` of an io failure or the `ureq::Error` of any other failure.
+pub type TransportErrorSource = Box<dyn Error + Send + Sync>;
 
 /// Default error enum for the crate, containing all the potential errors.
 #[derive(Debug)]
@@ -8,8 +14,37 @@ pub enum GmailnatorError {
     /// Stores the error code returned by the server.
     ServerError(u16),
 
+    /// Gets returned when the server could not be reached : dns failure, refused or reset connection, proxy failure...
+    ConnectionError(TransportErrorSource),
+
+    /// Gets returned when the server took longer to answer than the configured timeouts.
+    TimeoutError(TransportErrorSource),
+
+    /// Gets returned when the tls handshake or the encrypted stream failed.
+    TlsError(TransportErrorSource),
+
+    /// Gets returned when the response status was received but its body could not be read.
+    BodyReadError(io::Error),
+
+    /// Gets returned when the server throttles the client, stores the delay it asked to wait for if any.
+    RateLimitError(Option<Duration>),
+
+    /// Gets returned when an anti-bot challenge page (Cloudflare's "checking your browser") is served instead of the content, stores the delay to wait for if any.
+    ChallengeError(Option<Duration>),
+
+    /// Gets returned when a captcha page is served instead of the content, stores the delay to wait for if any.
+    CaptchaError(Option<Duration>),
+
     /// Stores the invalid gmail address returned by the server.
     MailServerParsingError(String),
+
+    /// Gets returned when an address given to the library is not a valid e-mail address.
+    InvalidAddressError {
+        /// The invalid address.
+        address:String,
+        /// What is wrong with it.
+        reason:String,
+    },
     
     /// Stores the unparsable html returned by the server.
     HtmlParsingError(String),
@@ -23,9 +58,83 @@ pub enum GmailnatorError {
     /// Stores the invalid Json returned by the server.
     JsonParsingError(String),
 
+    /// Gets returned when a `GmailnatorClient` is built with an invalid setting, stores the reason.
+    ClientConfigError(String),
+
+    /// Gets returned when a custom code pattern given to a `CodeExtractor` is not a valid regular expression.
+    InvalidPatternError {
+        /// The invalid pattern.
+        pattern:String,
+        /// Why it could not be compiled.
+        reason:String,
+    },
+
+    /// Gets returned by `GmailnatorInbox::wait_for_message` if no matching message was received in time, stores the timeout.
+    WaitTimeoutError(Duration),
+
+    /// Gets returned when exported messages could not be written.
+    WriteError(io::Error),
+
+    /// Gets returned when a chunk of a bulk creation fails, stores how many inboxes were created before it and the error of the chunk.
+    BulkCreationError {
+        /// The number of inboxes created before the failure.
+        created:u32,
+        /// The number of inboxes that were asked for.
+        requested:u32,
+        /// The error of the failed chunk.
+        source:Box<GmailnatorError>,
+    },
+
 }
 
-impl Error for GmailnatorError {}
+impl GmailnatorError {
+
+    /// Returns true if the server refused to serve the client : rate limit, anti-bot challenge or captcha.
+    pub fn is_blocking_error(&self) -> bool {
+        matches!(self, GmailnatorError::RateLimitError(_) | GmailnatorError::ChallengeError(_) | GmailnatorError::CaptchaError(_))
+    }
+
+    /// Gets the delay the server asked to wait for before sending requests again.
+    pub fn get_retry_after(&self) -> Option<Duration> {
+
+        match self {
+            GmailnatorError::RateLimitError(delay) | GmailnatorError::ChallengeError(delay) | GmailnatorError::CaptchaError(delay) => *delay,
+            _ => None,
+        }
+
+    }
+
+    /// Returns true if no usable response was received, as opposed to an error sent or caused by the server.
+    pub fn is_transport_error(&self) -> bool {
+
+        matches!(self,
+            GmailnatorError::ConnectionError(_) |
+            GmailnatorError::TimeoutError(_) |
+            GmailnatorError::TlsError(_) |
+            GmailnatorError::BodyReadError(_)
+        )
+
+    }
+
+}
+
+impl Error for GmailnatorError {
+
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+
+        match &self {
+            GmailnatorError::ConnectionError(source) => Some(source.as_ref()),
+            GmailnatorError::TimeoutError(source) => Some(source.as_ref()),
+            GmailnatorError::TlsError(source) => Some(source.as_ref()),
+            GmailnatorError::BodyReadError(source) => Some(source),
+            GmailnatorError::WriteError(source) => Some(source),
+            GmailnatorError::BulkCreationError {source, ..} => Some(source.as_ref()),
+            _ => None,
+        }
+
+    }
+
+}
 
 impl fmt::Display for GmailnatorError {
 
@@ -33,11 +142,24 @@ impl fmt::Display for GmailnatorError {
     
         let error_message = match &self {
             GmailnatorError::MailServerParsingError(address) => format!("Could not parse mail server of : {}", address),
+            GmailnatorError::InvalidAddressError {address, reason} => format!("Invalid e-mail address \"{}\" : {}", address, reason),
             GmailnatorError::ServerError(error_code) => format!("Server error-ed with status code : {}", error_code),
+            GmailnatorError::ConnectionError(source) => format!("Could not connect to the server : {}", source),
+            GmailnatorError::TimeoutError(source) => format!("The server did not answer in time : {}", source),
+            GmailnatorError::TlsError(source) => format!("Tls error : {}", source),
+            GmailnatorError::BodyReadError(source) => format!("Could not read the response body : {}", source),
+            GmailnatorError::RateLimitError(delay) => format!("The server is rate limiting the requests (retry after : {:?})", delay),
+            GmailnatorError::ChallengeError(delay) => format!("An anti-bot challenge was served instead of the content (retry after : {:?})", delay),
+            GmailnatorError::CaptchaError(delay) => format!("A captcha was served instead of the content (retry after : {:?})", delay),
             GmailnatorError::HtmlParsingError(html) => format!("Error occured while parsing the following html element : {}", html),
             GmailnatorError::InvalidCountError(count_value) => format!("Count argument has an invalid value ({})", count_value),
             GmailnatorError::HtmlDecodingError => "Malformed html entities were encountered.".to_string(),
             GmailnatorError::JsonParsingError(json) => format!("Invalid json string : {}", json),
+            GmailnatorError::ClientConfigError(reason) => format!("Invalid client configuration : {}", reason),
+            GmailnatorError::InvalidPatternError {pattern, reason} => format!("Invalid code pattern \"{}\" : {}", pattern, reason),
+            GmailnatorError::WaitTimeoutError(timeout) => format!("No matching message was received within {:?}", timeout),
+            GmailnatorError::WriteError(source) => format!("Could not write the exported messages : {}", source),
+            GmailnatorError::BulkCreationError {created, requested, source} => format!("Bulk creation stopped after {} of {} inboxes : {}", created, requested, source),
         };
 
         write!(f, "{}", error_message)
@@ -0,0 +1,259 @@
+use crate::mail::{GmailnatorInbox, MailMessage, Error};
+
+use chrono::{DateTime, Utc};
+
+use std::fs;
+use std::path::Path;
+use std::collections::HashSet;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// The domain of the synthesized message ids.
+const MESSAGE_ID_DOMAIN:&str = "gmailnator.com";
+
+/// The sender of the messages whose sender is unknown.
+const UNKNOWN_SENDER:&str = "unknown@gmailnator.invalid";
+
+/// The folders of a maildir.
+const MAILDIR_FOLDERS:[&str; 3] = ["tmp", "new", "cur"];
+
+/// The end of the file names of the synced messages, following their key.
+const MAILDIR_SUFFIX:&str = ".gmailnator";
+
+/// Length of the base64 lines of the body, as recommended by RFC 2045.
+const BASE64_LINE_LENGTH:usize = 76;
+
+/// Number of bytes encoded in each encoded word of a header, so that the words fit in 75 characters.
+const ENCODED_WORD_BYTES:usize = 45;
+
+const BASE64_ALPHABET:&[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Writes `message` as an RFC 5322 message with CRLF line endings, its html content being base64 encoded.
+/// `recipient` is used as the `To` header if the message doesn't tell who it was sent to.
+pub(crate) fn to_eml(message:&MailMessage, recipient:Option<&str>) -> String {
+
+    let date = message.get_received_time().unwrap_or_else(Utc::now);
+
+    let from = match (message.get_sender_name(), message.get_sender_address()) {
+
+        (Some(name), Some(address)) => format!("{} <{}>", encode_display_name(name), sanitize(address)),
+        (None, Some(address)) => sanitize(address),
+        (Some(name), None) => format!("{} <{}>", encode_display_name(name), UNKNOWN_SENDER),
+        (None, None) => UNKNOWN_SENDER.to_string(),
+
+    };
+
+    let mut headers = vec![
+        ("From", from),
+        ("Subject", encode_header(message.get_subject())),
+        ("Date", date.to_rfc2822()),
+        ("Message-ID", format!("<{}@{}>", get_message_key(message), MESSAGE_ID_DOMAIN)),
+        ("MIME-Version", "1.0".to_string()),
+        ("Content-Type", "text/html; charset=utf-8".to_string()),
+        ("Content-Transfer-Encoding", "base64".to_string()),
+    ];
+
+    if let Some(to) = message.get_recipient().or(recipient) {
+        headers.insert(1, ("To", sanitize(to)));
+    }
+
+    let mut eml = String::new();
+
+    for (name, value) in headers {
+        eml.push_str(&format!("{}: {}\r\n", name, value));
+    }
+
+    eml.push_str("\r\n");
+
+    let body = encode_base64(message.get_raw_content().as_bytes());
+
+    for line in body.as_bytes().chunks(BASE64_LINE_LENGTH) {
+        eml.push_str(std::str::from_utf8(line).unwrap_or_default());
+        eml.push_str("\r\n");
+    }
+
+    eml
+
+}
+
+/// Mirrors the messages of an inbox in the maildir at `path`, creating its `tmp`, `new` and `cur` folders if needed.
+/// Each message is stored in a file named after its server id, so the messages found in `new` or `cur` are never fetched again.
+/// Returns the number of messages added.
+pub(crate) fn sync_maildir(inbox:&GmailnatorInbox, path:&Path) -> Result<usize, Error> {
+
+    for folder in &MAILDIR_FOLDERS {
+        fs::create_dir_all(path.join(folder)).map_err(Error::WriteError)?;
+    }
+
+    let mut stored = HashSet::new();
+
+    for folder in &["new", "cur"] {
+
+        for entry in fs::read_dir(path.join(folder)).map_err(Error::WriteError)? {
+
+            let name = entry.map_err(Error::WriteError)?.file_name().to_string_lossy().to_string();
+
+            //Mail clients append the flags of a message after a ':' when moving it to cur
+            let unique = name.split(':').next().unwrap_or_default();
+
+            if let Some(key) = unique.strip_suffix(MAILDIR_SUFFIX) {
+                stored.insert(key.to_string());
+            }
+
+        }
+
+    }
+
+    let mut added = 0;
+
+    for summary in inbox.get_inbox_message_summaries()? {
+
+        if stored.contains(&get_id_key(&summary.id)) {
+            continue;
+        }
+
+        let message = GmailnatorInbox::get_message_by_id(inbox.get_client(), &inbox.temp_server, &summary)?;
+        let key = get_message_key(&message);
+
+        let file_name = format!("{}{}", key, MAILDIR_SUFFIX);
+        let temporary = path.join("tmp").join(&file_name);
+
+        //Messages are written in tmp then moved, so that a reader never sees a partial message
+        fs::write(&temporary, to_eml(&message, Some(inbox.get_address())))
+           .and_then(|_| fs::rename(&temporary, path.join("new").join(&file_name)))
+           .map_err(Error::WriteError)?;
+
+        stored.insert(key);
+        added += 1;
+
+    }
+
+    Ok(added)
+
+}
+
+/// Writes `message` as an entry of an mboxrd file : a `From ` separator line followed by the message with LF line endings,
+/// its lines starting with `From ` (after any number of `>`) being quoted with one more `>`.
+pub(crate) fn to_mbox_entry(message:&MailMessage, recipient:Option<&str>) -> String {
+
+    let date:DateTime<Utc> = message.get_received_time().unwrap_or_else(Utc::now);
+    let sender = message.get_sender_address().map(sanitize).filter(|sender| !sender.contains(' ')).unwrap_or_else(|| UNKNOWN_SENDER.to_string());
+
+    let mut entry = format!("From {} {}\n", sender, date.format("%a %b %e %H:%M:%S %Y"));
+
+    for line in to_eml(message, recipient).split("\r\n") {
+
+        if line.trim_start_matches('>').starts_with("From ") {
+            entry.push('>');
+        }
+
+        entry.push_str(line);
+        entry.push('\n');
+
+    }
+
+    entry
+
+}
+
+/// A key identifying the message : its server id, or a hash of its content for the messages without one.
+/// Only the characters allowed in the file names of a maildir and in message ids are kept.
+pub(crate) fn get_message_key(message:&MailMessage) -> String {
+
+    match message.get_id().map(get_id_key) {
+
+        Some(id) if !id.is_empty() => id,
+        _ => {
+
+            let mut hasher = DefaultHasher::new();
+
+            message.get_subject().hash(&mut hasher);
+            message.get_raw_content().hash(&mut hasher);
+
+            format!("{:016x}", hasher.finish())
+
+        },
+
+    }
+
+}
+
+/// Keeps the characters of a server id that are allowed in the file names of a maildir and in message ids.
+pub(crate) fn get_id_key(id:&str) -> String {
+    id.chars().filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_').collect()
+}
+
+/// Removes the line breaks of a header value, which would start new headers.
+fn sanitize(value:&str) -> String {
+    value.replace(['\r', '\n'], " ").trim().to_string()
+}
+
+/// Encodes a header value in RFC 2047 encoded words if it is not plain printable ascii.
+fn encode_header(value:&str) -> String {
+
+    let value = sanitize(value);
+
+    if value.len() < 900 && value.chars().all(|c| c == ' ' || c.is_ascii_graphic()) {
+        return value;
+    }
+
+    let mut words = Vec::new();
+    let mut chunk = String::new();
+
+    for c in value.chars() {
+
+        if chunk.len() + c.len_utf8() > ENCODED_WORD_BYTES {
+            words.push(format!("=?UTF-8?B?{}?=", encode_base64(chunk.as_bytes())));
+            chunk.clear();
+        }
+
+        chunk.push(c);
+
+    }
+
+    if !chunk.is_empty() {
+        words.push(format!("=?UTF-8?B?{}?=", encode_base64(chunk.as_bytes())));
+    }
+
+    words.join("\r\n ")
+
+}
+
+/// Quotes or encodes a display name so that it can't be mistaken for an address.
+fn encode_display_name(name:&str) -> String {
+
+    let name = sanitize(name);
+
+    if name.chars().all(|c| c.is_ascii_alphanumeric() || c == ' ') {
+        name
+    } else if name.chars().all(|c| c == ' ' || c.is_ascii_graphic()) {
+        format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        encode_header(&name)
+    }
+
+}
+
+fn encode_base64(bytes:&[u8]) -> String {
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+
+        let block = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+
+        for index in 0..4 {
+
+            if index <= chunk.len() {
+                encoded.push(BASE64_ALPHABET[(block >> (18 - 6 * index) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+
+        }
+
+    }
+
+    encoded
+
+}
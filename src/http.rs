@@ -1,12 +1,37 @@
 use url::form_urlencoded::byte_serialize;
-use ureq::{Response, Request};
+use crate::endpoint::GmailnatorRequest;
+use crate::transport::{HttpTransport, HttpResponse};
 use crate::mail::Error;
+use crate::retry::{RetryPolicy, parse_retry_after};
 
-/// Defines the maximum retry count if an http response indicates an `internal server error (500)`
-const QUERY_MAX_TRY:u32 = 2;
+use chrono::Utc;
+use scraper::{Html, Selector};
 
-/// The internal server error http error code sent back by the server.
-const INTERNAL_SERVER_ERROR:u16 = 500;
+use std::thread;
+use std::time::Duration;
+
+/// Defines the default maximum number of attempts of a request, see [`RetryPolicy`](struct.RetryPolicy.html).
+pub const QUERY_MAX_TRY:u32 = 2;
+
+/// The too many requests http error code.
+const TOO_MANY_REQUESTS:u16 = 429;
+
+/// Markers of the Cloudflare "checking your browser" interstitial.
+/// The `/cdn-cgi/challenge-platform/` scripts are not one of them : Cloudflare embeds them in the regular pages too.
+const CLOUDFLARE_CHALLENGE_MARKERS:[&str; 2] = ["cf_chl_opt", "<title>just a moment...</title>"];
+
+/// Markers of the pages served to throttled clients.
+const RATE_LIMIT_MARKERS:[&str; 2] = ["you are being rate limited", "error code: 1015"];
+
+/// Markers of the captcha widgets.
+const CAPTCHA_MARKERS:[&str; 5] = ["g-recaptcha", "google.com/recaptcha/api.js", "h-captcha", "hcaptcha.com/1/api.js", "cf-turnstile"];
+
+lazy_static! {
+
+    /// The content of the full pages the site answers with, the bulk creation page and the landing page, which embed the site-wide scripts and widgets.
+    static ref EXPECTED_CONTENT_SELECTOR:Selector = Selector::parse("#email-list-message, input[name=csrf_gmailnator_token]").unwrap();
+
+}
 
 pub fn url_encode(s:&str) -> String {
 
@@ -16,88 +41,177 @@ pub fn url_encode(s:&str) -> String {
 
 }
 
-pub fn get_error(server_response:&Response) -> Option<u16> {
+/// Outcome of a single request attempt.
+pub enum Attempt {
 
-    if server_response.error() {
+    /// The request is over, whether it succeeded or not.
+    Done(Result<String, Error>),
 
-        Some(server_response.status())
+    /// The request failed but may succeed if sent again, after the delay asked by the server if any.
+    Retry(Error, Option<Duration>),
 
-    } else {
+}
 
-        None
+/// Returns true if a failure to get a response may not happen again : tls and configuration errors are permanent.
+fn is_transient(error:&Error) -> bool {
+    !matches!(error, Error::TlsError(_) | Error::ClientConfigError(_))
+}
+
+/// Gets the delay asked by the `Retry-After` header of the response.
+pub fn get_retry_after(response:&HttpResponse) -> Option<Duration> {
+    response.get_header("Retry-After").and_then(|value| parse_retry_after(value, Utc::now()))
+}
+
+/// The error matching the error status of `response`, rate limits being told apart from the other errors.
+pub fn get_status_error(response:&HttpResponse) -> Error {
+
+    match response.get_status() {
+
+        TOO_MANY_REQUESTS => Error::RateLimitError(get_retry_after(response)),
+        status => Error::ServerError(status),
 
     }
 
 }
 
-pub fn get_response_content(mut request:Request, query:UrlQuery) -> Result<String, Error> {
+/// Recognizes the pages served instead of the expected content, whatever their status, when the client is throttled or taken for a bot.
+/// Only html documents are inspected, so that the content of an e-mail can't be mistaken for such a page.
+pub fn get_blocking_error(response:&HttpResponse, check_captcha:bool) -> Option<Error> {
 
-    let mut last_error:Error = Error::ServerError(0);
+    let retry_after = get_retry_after(response);
 
-    let payload = query.to_query_string();
+    if response.get_header("cf-mitigated").map(|value| value.eq_ignore_ascii_case("challenge")) == Some(true) {
+        return Some(Error::ChallengeError(retry_after));
+    }
 
-    for _ in 0..QUERY_MAX_TRY {
+    let body = response.get_body().trim_start().to_lowercase();
 
-        let response = request.send_string(&payload);
+    if !body.starts_with("<!doctype html") && !body.starts_with("<html") {
+        return None;
+    }
 
-        if let Some(error_code) = get_error(&response) { //If error gets returned
+    let contains_any = |markers:&[&str]| markers.iter().any(|marker| body.contains(marker));
 
-            last_error = Error::ServerError(error_code); //Set last error whatever it is
+    //The page is only parsed if it looks like a blocking page
+    let is_missing_content = || !has_expected_content(response.get_body());
 
-            if error_code == INTERNAL_SERVER_ERROR { //If it is an internal server error we iterate one more time if 'available'
+    if contains_any(&CLOUDFLARE_CHALLENGE_MARKERS) && is_missing_content() {
+        Some(Error::ChallengeError(retry_after))
+    } else if contains_any(&RATE_LIMIT_MARKERS) {
+        Some(Error::RateLimitError(retry_after))
+    } else if check_captcha && contains_any(&CAPTCHA_MARKERS) && is_missing_content() {
+        Some(Error::CaptchaError(retry_after))
+    } else {
+        None
+    }
 
-                continue;
+}
 
-            } else { //Else we return directly the error because it's most likely not gonna get fixed by re-requesting
+/// Returns true if the page holds the content that was requested, in which case a captcha widget is only part of the site layout.
+fn has_expected_content(body:&str) -> bool {
+    Html::parse_document(body).select(&EXPECTED_CONTENT_SELECTOR).next().is_some()
+}
 
-                return Err(last_error)
+pub fn evaluate_attempt(outcome:Result<HttpResponse, Error>, policy:&RetryPolicy) -> Attempt {
 
-            }
+    let response = match outcome {
+
+        Ok(response) => response,
+        Err(error) if policy.is_retrying_transport_errors() && is_transient(&error) => { return Attempt::Retry(error, None); }
+        Err(error) => { return Attempt::Done(Err(error)); }
+
+    };
+
+    if let Some(error) = get_blocking_error(&response, true) { //Challenges won't go away by re-requesting right away, the caller has to back off
+        return Attempt::Done(Err(error));
+    }
+
+    if response.is_error() { //If error gets returned
 
-        } else { //Return the response content if request succeeded
+        let error_code = response.get_status();
 
-            return Ok(response.into_string().unwrap_or_default());
+        if policy.is_retryable_status(error_code) { //If the policy allows it we iterate one more time if 'available'
+
+            Attempt::Retry(get_status_error(&response), get_retry_after(&response))
+
+        } else { //Else we return directly the error because it's most likely not gonna get fixed by re-requesting
+
+            Attempt::Done(Err(get_status_error(&response)))
+
+        }
+
+    } else { //Return the response content if request succeeded
+
+        Attempt::Done(Ok(response.into_body()))
+
+    }
+
+}
+
+pub fn get_response_content(transport:&dyn HttpTransport, request:GmailnatorRequest, query:UrlQuery, policy:&RetryPolicy) -> Result<String, Error> {
+
+    let payload = query.into_query_string();
+
+    let mut attempt:u32 = 0;
+
+    loop {
+
+        attempt += 1;
+
+        match evaluate_attempt(transport.send(&request, &payload), policy) {
+
+            Attempt::Done(result) => { return result; }
+            Attempt::Retry(error, retry_after) => match policy.get_delay(attempt, retry_after) {
+
+                Some(delay) => thread::sleep(delay),
+                None => { return Err(error); } //If multiples tries weren't enough, desesperately return the error :^(
+
+            }
 
         }
 
     }
 
-    Err(last_error) //If multiples tries weren't enough, desesperately return the error :^(
-    
 }
 
+#[derive(Clone, Default)]
 pub struct UrlQuery {
 
-    query_string:String,
+    entries:Vec<(String, String)>,
 
 }
 
 impl UrlQuery {
 
     pub fn new() -> Self {
-        Self {
-            query_string:String::default()
-        }
+        Self::default()
     }
 
     pub fn add(&mut self, key:&str, value:&str) {
 
-        let is_first_entry = self.query_string.is_empty();
+        self.entries.push((key.to_string(), value.to_string()));
 
-        let encoded_value = url_encode(value);
+    }
 
-        if !is_first_entry {
-            self.query_string.push('&');
-        }
+    /// Replaces the value of the `key` entry, or adds it if the query doesn't contain it.
+    pub fn set(&mut self, key:&str, value:&str) {
+
+        match self.entries.iter_mut().find(|(entry_key, _)| entry_key == key) {
 
-        self.query_string.push_str(key);
-        self.query_string.push('=');
-        self.query_string.push_str(&encoded_value);
+            Some(entry) => entry.1 = value.to_string(),
+            None => self.add(key, value),
+
+        }
 
     }
 
-    pub fn to_query_string(self) -> String {
-        self.query_string
+    pub fn into_query_string(self) -> String {
+
+        self.entries.iter()
+                    .map(|(key, value)| format!("{}={}", key, url_encode(value)))
+                    .collect::<Vec<String>>()
+                    .join("&")
+
     }
 
-}
\ No newline at end of file
+}
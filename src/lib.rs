@@ -1,26 +1,29 @@
 //! [`GmailnatorInbox`]: struct.GmailnatorInbox.html
 //! [`MailMessage`]: struct.MailMessage.html
 //! [`MailMessageIterator`]: struct.MailMessageIterator.html
+//! [`HttpTransport`]: trait.HttpTransport.html
+//! [`GmailnatorClient`]: struct.GmailnatorClient.html
+//! [`MockServer`]: struct.MockServer.html
 //! This library contains objects to create a gmailnator inbox and read the messages it contains.
 //! # Getting started : 
 //! The main struct is the [`GmailnatorInbox`] struct, one instance contains one inbox associated to an email address.
 //! 
 //! This creates a new temporary gmail address :
-//! ```
+//! ```no_run
 //! use gmailnator::GmailnatorInbox;
 //! 
 //! let inbox = GmailnatorInbox::new().unwrap();
 //! ```
 //! 
 //! To get the associated mail address :
-//! ```
+//! ```no_run
 //! # use gmailnator::GmailnatorInbox;
 //! # let inbox = GmailnatorInbox::new().unwrap();
 //! let address:&str = inbox.get_address();
 //! ```
 //! 
 //! This creates `n` number of addresses, it must be used to create a large number of inboxes.
-//! ```
+//! ```no_run
 //! use gmailnator::GmailnatorInbox;
 //!
 //! let n:u32 = 500;
@@ -30,7 +33,7 @@
 //! ```
 //! 
 //! Retrieve messages in a vector and display them via the container struct [`MailMessage`]:
-//! ```
+//! ```no_run
 //! use gmailnator::{GmailnatorInbox, MailMessage};
 //! # let inbox = GmailnatorInbox::new().unwrap();
 //! let messages:Vec<MailMessage> = inbox.get_messages_iter().unwrap().collect();
@@ -51,7 +54,7 @@
 //! ```
 //! 
 //! To search for a particular message, use the [`MailMessageIterator`] :
-//! ```
+//! ```no_run
 //! use gmailnator::{GmailnatorInbox, MailMessage, MailMessageIterator};
 //! # let inbox = GmailnatorInbox::new().unwrap();
 //! let mut messages_iter:MailMessageIterator = inbox.get_messages_iter().unwrap();
@@ -64,6 +67,64 @@
 //! 
 //! }
 //!  ```
+//! 
+//! # Client configuration :
+//! The functions above use a default [`GmailnatorClient`], a differently configured one can be built and used to create inboxes :
+//! ```no_run
+//! use std::time::Duration;
+//! use gmailnator::GmailnatorClient;
+//! 
+//! let client = GmailnatorClient::builder()
+//!                              .user_agent("my-user-agent")
+//!                              .connect_timeout(Duration::from_secs(5))
+//!                              .build()
+//!                              .unwrap();
+//! 
+//! let inbox = client.new_inbox().unwrap();
+//! ```
+//! 
+//! Every request goes through an [`HttpTransport`], `ureq` being used by default. 
+//! A custom transport can be given to the client to reach a local stand-in server or another http stack :
+//! ```
+//! use std::sync::Arc;
+//! use gmailnator::{GmailnatorClient, UreqTransport};
+//! 
+//! let client = GmailnatorClient::builder().transport(Arc::new(UreqTransport::new())).build().unwrap();
+//! ```
+//! 
+//! # Async :
+//! With the `async` feature enabled, inboxes can be created and read without blocking, messages being delivered through a `Stream` :
+//! ```ignore
+//! use futures::StreamExt;
+//! use gmailnator::{GmailnatorInbox, MailMessage};
+//! 
+//! let inbox = GmailnatorInbox::new_async().await.unwrap();
+//! 
+//! let messages:Vec<MailMessage> = inbox.get_messages_stream().await.unwrap().collect().await;
+//! ```
+//! 
+//! # Testing :
+//! With the `mock-server` feature enabled, a [`MockServer`] serves the gmailnator endpoints locally, its inboxes can be filled with any message :
+//! ```ignore
+//! use gmailnator::{MockServer, MockMessage};
+//! 
+//! let server = MockServer::start().unwrap();
+//! let inbox = server.client().new_inbox().unwrap();
+//! 
+//! server.inject_message(inbox.get_address(), MockMessage::new("Confirm your order", "<a href=\"https://example.com\">Confirm</a>"));
+//! ```
+//! 
+//! # Command line :
+//! The `cli` feature builds the `gmailnator` binary, every command prints json with `--json` :
+//! ```text
+//! cargo install gmailnator --features cli
+//! 
+//! gmailnator new
+//! gmailnator --json list extmp+abcd@gmail.com
+//! gmailnator show extmp+abcd@gmail.com 17a0c3f5
+//! gmailnator export extmp+abcd@gmail.com --format maildir --output ~/Maildir/.temp
+//! ```
+//! The `tui` feature adds `gmailnator tui [ADDRESS...]`, an interactive browser of the inboxes which refreshes them, creates new ones and copies their links and codes.
 
 
 #![warn(missing_docs)]
@@ -79,15 +140,46 @@ extern crate scraper;
 extern crate htmlescape;
 extern crate serde;
 extern crate serde_json;
+extern crate chrono;
+#[cfg(any(test, feature = "mock-server"))]
+extern crate tiny_http;
 
 mod errors;
 mod mail;
 mod endpoint;
 mod regexes;
 mod http;
+mod transport;
+mod client;
+mod session;
+mod bulk;
+mod address;
+mod retry;
+mod watch;
+mod render;
+mod links;
+mod codes;
+mod export;
+#[cfg(feature = "async")]
+mod async_api;
+#[cfg(any(test, feature = "mock-server"))]
+mod mock;
 
-pub use mail::{MailMessage, GmailnatorInbox, MailMessageIterator, Error};
-pub use errors::GmailnatorError;
+pub use links::MailLink;
+pub use codes::CodeExtractor;
+pub use mail::{MailMessage, GmailnatorInbox, MailMessageIterator, MailMessageTryIterator, Error};
+pub use errors::{GmailnatorError, TransportErrorSource};
+pub use endpoint::{GmailnatorRequest, HttpMethod};
+pub use client::{GmailnatorClient, GmailnatorClientBuilder};
+pub use bulk::BulkInboxIterator;
+pub use address::{AddressKind, EmailAddress, AliasIterator};
+pub use retry::RetryPolicy;
+pub use watch::{InboxWatcher, WatchSnapshot, MultiInboxWatcher, MultiInboxWatcherBuilder};
+pub use transport::{HttpTransport, HttpResponse, UreqTransport};
+#[cfg(feature = "async")]
+pub use async_api::{AsyncHttpTransport, PooledTransport, MailMessageStream, MailMessageTryStream, BulkInboxStream};
+#[cfg(any(test, feature = "mock-server"))]
+pub use mock::{MockServer, MockMessage, MockRequest};
 
 #[cfg(test)]
 mod passive_tests {
@@ -96,11 +188,14 @@ mod passive_tests {
     extern crate serde_json;
 
     use crate::mail::{GmailnatorInbox, MailMessage};
+    use crate::mock::{MockServer, MockMessage};
 
     #[test]
     fn create_inbox() {
 
-        let inbox = GmailnatorInbox::new().expect("Failed to create an inbox."); 
+        let server = MockServer::start().unwrap();
+
+        let inbox = server.client().new_inbox().expect("Failed to create an inbox."); 
 
         let address = inbox.get_address();
 
@@ -113,7 +208,9 @@ mod passive_tests {
     #[test]
     fn retrieve_messages_iter() {
 
-      let inbox = GmailnatorInbox::new().unwrap();
+      let server = MockServer::start().unwrap();
+
+      let inbox = server.client().new_inbox().unwrap();
 
       let mut message_iter = inbox.get_messages_iter().unwrap();
 
@@ -121,13 +218,54 @@ mod passive_tests {
 
     }
 
+    #[test]
+    fn retrieve_injected_messages() {
+
+        let server = MockServer::start().unwrap();
+
+        let inbox = server.client().new_inbox().unwrap();
+
+        let id = server.inject_message(inbox.get_address(), MockMessage::new("Welcome", "<p>Hi &amp; bye</p>").with_sender("Team <team@example.com>").with_received("2 hrs ago"));
+        server.inject_message(&server.create_address(), MockMessage::new("Someone else's", ""));
+
+        let messages:Vec<MailMessage> = inbox.get_messages_iter().unwrap().collect();
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].get_subject(), "Welcome");
+        assert_eq!(messages[0].get_id(), Some(id.as_str()));
+        assert_eq!(messages[0].get_sender_address(), Some("team@example.com"));
+        assert_eq!(messages[0].decode_content().unwrap(), "<p>Hi & bye</p>");
+        assert!(messages[0].get_received_time().is_some());
+
+    }
+
+    #[test]
+    fn mock_server_failures_and_csrf() {
+
+        let server = MockServer::start().unwrap();
+
+        let client = server.client();
+        let inbox = client.new_inbox().unwrap();
+
+        server.rotate_csrf_token();
+        assert!(inbox.get_messages_iter().is_ok());
+
+        server.fail_next(502, 2);
+        assert!(matches!(client.new_inbox(), Err(crate::Error::ServerError(502))));
+
+        assert!(server.get_requests().iter().all(|request| request.method == "GET" || request.get_field("csrf_gmailnator_token").is_some()));
+
+    }
+
     #[test]
     fn create_inbox_from_existing_address() {
 
-        let new_address = GmailnatorInbox::new().unwrap();
+        let server = MockServer::start().unwrap();
+
+        let new_address = server.client().new_inbox().unwrap();
         let new_address = new_address.get_address();
         
-        let inbox = GmailnatorInbox::from_address(new_address).unwrap();
+        let inbox = server.client().inbox_from_address(new_address).unwrap();
 
         assert_eq!(inbox.get_address(), new_address);
 
@@ -136,9 +274,11 @@ mod passive_tests {
     #[test]
     fn create_bulk() {
         
+        let server = MockServer::start().unwrap();
+
         let count:u32 = 1;
 
-        let inboxes = GmailnatorInbox::new_bulk(count).unwrap();   
+        let inboxes = server.client().new_bulk(count).unwrap();   
 
         assert_eq!(inboxes.len() as u32, count);
 
@@ -147,9 +287,11 @@ mod passive_tests {
     #[test]
     fn create_bulk_larger() {
         
+        let server = MockServer::start().unwrap();
+
         let count:u32 = 1000;
 
-        let inboxes = GmailnatorInbox::new_bulk(count).unwrap();   
+        let inboxes = server.client().new_bulk(count).unwrap();   
 
         assert_eq!(inboxes.len() as u32, count);
 
@@ -192,4 +334,1605 @@ mod passive_tests {
 
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn parse_mail_message_details() {
+
+        let json = "{\"subject\":\"<b>subject<\\/b><div>John &lt;john@example.com&gt;<\\/div><div>2 min ago<hr \\/><\\/div>\",\"content\":\"content\"}";
+
+        let message = MailMessage::parse(json).unwrap();
+
+        assert_eq!(message.get_sender_name(), Some("John"));
+        assert_eq!(message.get_sender_address(), Some("john@example.com"));
+
+        let age = chrono::Utc::now() - message.get_received_time().unwrap();
+
+        assert!(age >= chrono::Duration::minutes(2) && age < chrono::Duration::minutes(3));
+
+    }
+
+    #[test]
+    fn parse_received_times() {
+
+        use crate::mail::parse_received_time;
+        use chrono::{Duration, Utc};
+
+        let now = Utc::now();
+
+        assert_eq!(parse_received_time("5 hrs ago", now), Some(now - Duration::hours(5)));
+        assert_eq!(parse_received_time("an hour ago", now), Some(now - Duration::hours(1)));
+        assert_eq!(parse_received_time("1 day ago", now), Some(now - Duration::days(1)));
+        assert_eq!(parse_received_time("just now", now), Some(now));
+        assert_eq!(parse_received_time("yesterday-ish", now), None);
+
+    }
+
+}
+
+#[cfg(test)]
+mod transport_tests {
+
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use crate::{GmailnatorClient, GmailnatorRequest, HttpTransport, HttpResponse, HttpMethod, Error};
+
+    /// Answers each request with the response registered for the end of its url and records the requests it received.
+    pub(crate) struct FakeTransport {
+        routes:Vec<(&'static str, HttpResponse)>,
+        pub(crate) requests:Mutex<Vec<(GmailnatorRequest, String)>>,
+    }
+
+    impl FakeTransport {
+
+        pub(crate) fn new(routes:Vec<(&'static str, HttpResponse)>) -> Arc<Self> {
+            Arc::new(Self {routes, requests:Mutex::new(Vec::new())})
+        }
+
+        /// Returns the payload of the `index`-th POST request.
+        pub(crate) fn payload(&self, index:usize) -> String {
+
+            let requests = self.requests.lock().unwrap();
+
+            requests.iter()
+                    .filter(|(request, _)| request.get_method() == HttpMethod::Post)
+                    .nth(index)
+                    .map(|(_, payload)| payload.clone())
+                    .unwrap()
+
+        }
+
+    }
+
+    impl HttpTransport for FakeTransport {
+
+        fn send(&self, request:&GmailnatorRequest, body:&str) -> Result<HttpResponse, Error> {
+
+            self.requests.lock().unwrap().push((request.clone(), body.to_string()));
+
+            let route = self.routes.iter().find(|(suffix, _)| request.get_url().ends_with(suffix));
+
+            let fallback = match request.get_method() {
+                HttpMethod::Get => HttpResponse::new(200, ""), //Landing page without csrf token
+                HttpMethod::Post => HttpResponse::new(404, ""),
+            };
+
+            Ok(route.map(|(_, response)| response.clone()).unwrap_or(fallback))
+
+        }
+
+    }
+
+    pub(crate) fn fake_client(transport:Arc<FakeTransport>) -> GmailnatorClient {
+
+        let no_delay = crate::RetryPolicy::new().with_backoff(Duration::from_millis(0), Duration::from_millis(0));
+
+        GmailnatorClient::builder().transport(transport).retry_policy(no_delay).build().unwrap()
+
+    }
+
+    pub(crate) const MAILBOX_LIST:&str = r#"[{"content":"<a href=\"https:\/\/www.gmailnator.com\/fake\/messageid\/#17a0\"><table class=\"message_container\"><tbody><tr><td>Sender &lt;sender@example.com&gt;<\/td><td>Hello<\/td><td class=\"text-right\">3 hrs ago<\/td><\/tr><\/tbody><\/table><\/a>"}]"#;
+    pub(crate) const SINGLE_MESSAGE:&str = r#"{"subject":"<b>Hello<\/b><div>1 min ago<hr \/><\/div>","content":"<div dir=\"ltr\">World<\/div>"}"#;
+
+    #[test]
+    fn create_inbox_through_transport() {
+
+        let transport = FakeTransport::new(vec![("/index/indexquery", HttpResponse::new(200, "fake+abc@gmail.com"))]);
+
+        let inbox = fake_client(transport.clone()).new_inbox().unwrap();
+
+        assert_eq!(inbox.get_address(), "fake+abc@gmail.com");
+        assert!(transport.payload(0).contains("action=GenerateEmail"));
+
+    }
+
+    #[test]
+    fn retrieve_messages_through_transport() {
+
+        let transport = FakeTransport::new(vec![
+            ("/mailbox/mailboxquery", HttpResponse::new(200, MAILBOX_LIST)),
+            ("/mailbox/get_single_message", HttpResponse::new(200, SINGLE_MESSAGE)),
+        ]);
+
+        let inbox = fake_client(transport.clone()).inbox_from_address("fake+abc@gmail.com").unwrap();
+
+        let messages:Vec<_> = inbox.get_messages_iter().unwrap().collect();
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].get_subject(), "Hello");
+        assert_eq!(messages[0].get_id(), Some("17a0"));
+        assert_eq!(messages[0].get_sender_name(), Some("Sender"));
+        assert_eq!(messages[0].get_sender_address(), Some("sender@example.com"));
+        assert!(messages[0].get_received_time().is_some());
+        assert!(transport.payload(1).contains("message_id=17a0"));
+
+    }
+
+    #[test]
+    fn create_bulk_through_transport() {
+
+        let html = "<div id=\"email-list-message\"><a>one+a@gmail.com</a><a>two+b@gmail.com</a></div>";
+
+        let transport = FakeTransport::new(vec![("/bulk-emails", HttpResponse::new(200, html))]);
+
+        let inboxes = fake_client(transport).new_bulk(2).unwrap();
+
+        assert_eq!(inboxes.len(), 2);
+        assert_eq!(inboxes[1].get_address(), "two+b@gmail.com");
+
+    }
+
+    #[test]
+    fn message_errors_are_yielded() {
+
+        let transport = FakeTransport::new(vec![
+            ("/mailbox/mailboxquery", HttpResponse::new(200, MAILBOX_LIST)),
+            ("/mailbox/get_single_message", HttpResponse::new(502, "")),
+        ]);
+
+        let inbox = fake_client(transport).inbox_from_address("fake+abc@gmail.com").unwrap();
+
+        let results:Vec<_> = inbox.get_messages_try_iter().unwrap().collect();
+
+        assert_eq!(results.len(), 1);
+        assert!(matches!(results[0], Err(Error::ServerError(502))));
+
+        assert_eq!(inbox.get_messages_iter().unwrap().count(), 0);
+
+    }
+
+    #[test]
+    fn transport_failures_are_typed() {
+
+        use std::error::Error as _;
+        use std::net::TcpListener;
+        use crate::UreqTransport;
+
+        let request = GmailnatorRequest::from(HttpMethod::Get, "http://127.0.0.1:1/");
+
+        let refused = UreqTransport::new().send(&request, "").unwrap_err();
+
+        assert!(matches!(refused, Error::ConnectionError(_)));
+        assert!(refused.is_transport_error());
+
+        //The ureq error behind the failure is kept as the source
+        assert!(matches!(refused.source().and_then(|source| source.downcast_ref::<ureq::Error>()), Some(ureq::Error::ConnectionFailed(_))));
+
+        let silent_server = TcpListener::bind("127.0.0.1:0").unwrap(); //Accepts connections but never answers
+        let request = GmailnatorRequest::from(HttpMethod::Get, &format!("http://{}/", silent_server.local_addr().unwrap()));
+
+        let timed_out = UreqTransport::new().with_read_timeout(Duration::from_millis(100)).send(&request, "").unwrap_err();
+
+        assert!(matches!(timed_out, Error::TimeoutError(_)));
+        assert!(timed_out.source().unwrap().is::<std::io::Error>());
+
+        let unresolved = UreqTransport::new().send(&GmailnatorRequest::from(HttpMethod::Get, "http://host.invalid/"), "").unwrap_err();
+
+        assert!(matches!(unresolved.source().and_then(|source| source.downcast_ref::<ureq::Error>()), Some(ureq::Error::DnsFailed(_))));
+        assert!(!Error::ServerError(502).is_transport_error());
+
+    }
+
+    #[test]
+    fn server_error_through_transport() {
+
+        let transport = FakeTransport::new(vec![("/index/indexquery", HttpResponse::new(403, ""))]);
+
+        assert!(matches!(fake_client(transport).new_inbox(), Err(Error::ServerError(403))));
+
+    }
+
+}
+
+#[cfg(test)]
+mod wait_tests {
+
+    use std::time::Duration;
+
+    use crate::transport_tests::{FakeTransport, MAILBOX_LIST, SINGLE_MESSAGE, fake_client};
+    use crate::{HttpResponse, Error};
+
+    #[test]
+    fn wait_for_matching_message() {
+
+        let transport = FakeTransport::new(vec![
+            ("/mailbox/mailboxquery", HttpResponse::new(200, MAILBOX_LIST)),
+            ("/mailbox/get_single_message", HttpResponse::new(200, SINGLE_MESSAGE)),
+        ]);
+
+        let inbox = fake_client(transport).inbox_from_address("fake+abc@gmail.com").unwrap();
+
+        let message = inbox.wait_for_message(|m| m.get_subject() == "Hello", Duration::from_secs(1), Duration::from_millis(10)).unwrap();
+
+        assert_eq!(message.decode_content().unwrap(), "World");
+
+    }
+
+    #[test]
+    fn wait_times_out_without_refetching() {
+
+        let transport = FakeTransport::new(vec![
+            ("/mailbox/mailboxquery", HttpResponse::new(200, MAILBOX_LIST)),
+            ("/mailbox/get_single_message", HttpResponse::new(200, SINGLE_MESSAGE)),
+        ]);
+
+        let inbox = fake_client(transport.clone()).inbox_from_address("fake+abc@gmail.com").unwrap();
+
+        let result = inbox.wait_for_message(|_| false, Duration::from_millis(50), Duration::from_millis(10));
+
+        assert!(matches!(result, Err(Error::WaitTimeoutError(_))));
+
+        let requests = transport.requests.lock().unwrap();
+        let fetched = requests.iter().filter(|(request, _)| request.get_url().ends_with("/get_single_message")).count();
+
+        assert!(requests.len() > 2);
+        assert_eq!(fetched, 1);
+
+    }
+
+
+    #[test]
+    fn wait_goes_on_through_failed_polls() {
+
+        use std::time::Instant;
+        use crate::mock::{MockServer, MockMessage};
+        use crate::RetryPolicy;
+
+        let server = MockServer::start().unwrap();
+        let inbox = server.client_builder().retry_policy(RetryPolicy::never()).build().unwrap().new_inbox().unwrap();
+
+        server.inject_message(inbox.get_address(), MockMessage::new("Hello", ""));
+
+        //The first mailbox list fails, the second is rate limited for a second
+        server.respond_next(502, &[], "");
+        server.respond_next(429, &[("Retry-After", "1")], "");
+
+        let start = Instant::now();
+
+        let message = inbox.wait_for_message(|m| m.get_subject() == "Hello", Duration::from_secs(5), Duration::from_millis(10)).unwrap();
+
+        assert_eq!(message.get_subject(), "Hello");
+        assert!(start.elapsed() >= Duration::from_secs(1));
+
+    }
+
+}
+
+#[cfg(test)]
+mod session_tests {
+
+    use std::sync::{Arc, Mutex};
+
+    use crate::transport_tests::fake_client;
+    use crate::transport_tests::FakeTransport;
+    use crate::{GmailnatorClient, GmailnatorRequest, HttpTransport, HttpResponse, HttpMethod, Error};
+
+    const LANDING_PAGE:&str = "<form><input type=\"hidden\" name=\"csrf_gmailnator_token\" value=\"t0k3n\"></form>";
+
+    #[test]
+    fn csrf_token_and_cookie_are_sent() {
+
+        let transport = FakeTransport::new(vec![
+            ("/index/indexquery", HttpResponse::new(200, "fake+abc@gmail.com")),
+            ("gmailnator.com/", HttpResponse::new(200, LANDING_PAGE).with_header("Set-Cookie", "csrf_gmailnator_cookie=t0k3n; path=/")),
+        ]);
+
+        let client = fake_client(transport.clone());
+
+        client.new_inbox().unwrap();
+        client.new_inbox().unwrap();
+
+        let requests = transport.requests.lock().unwrap();
+
+        // The landing page is only loaded once per client
+        assert_eq!(requests.iter().filter(|(request, _)| request.get_method() == HttpMethod::Get).count(), 1);
+
+        let (request, payload) = &requests[2];
+
+        assert!(payload.starts_with("csrf_gmailnator_token=t0k3n&"));
+        assert_eq!(request.get_header("Cookie"), Some("csrf_gmailnator_cookie=t0k3n;"));
+
+    }
+
+    /// Rejects the requests that don't carry the token of the current landing page, which changes on each load.
+    struct RotatingTokenTransport {
+        generation:Mutex<u32>,
+        rejected:Mutex<u32>,
+    }
+
+    impl HttpTransport for RotatingTokenTransport {
+
+        fn send(&self, request:&GmailnatorRequest, body:&str) -> Result<HttpResponse, Error> {
+
+            let mut generation = self.generation.lock().unwrap();
+
+            if request.get_method() == HttpMethod::Get {
+
+                *generation += 1;
+
+                let token = format!("token{}", generation);
+
+                return Ok(HttpResponse::new(200, "").with_header("Set-Cookie", &format!("csrf_gmailnator_cookie={}", token)));
+
+            }
+
+            let expected = format!("csrf_gmailnator_token=token{}&", generation);
+
+            // The first token expires as soon as it is used
+            if *generation == 1 || !body.starts_with(&expected) {
+                *self.rejected.lock().unwrap() += 1;
+                return Ok(HttpResponse::new(403, "The action you have requested is not allowed."));
+            }
+
+            Ok(HttpResponse::new(200, "fake+abc@gmail.com"))
+
+        }
+
+    }
+
+    #[test]
+    fn rejected_session_is_refreshed() {
+
+        let transport = Arc::new(RotatingTokenTransport {generation:Mutex::new(0), rejected:Mutex::new(0)});
+
+        let client = GmailnatorClient::builder().transport(transport.clone()).build().unwrap();
+
+        let inbox = client.new_inbox().unwrap();
+
+        assert_eq!(inbox.get_address(), "fake+abc@gmail.com");
+        assert_eq!(*transport.generation.lock().unwrap(), 2);
+        assert_eq!(*transport.rejected.lock().unwrap(), 1);
+
+    }
+
+    /// Gives a different token and cookie to each landing page load, and rejects the requests whose token doesn't match their cookie.
+    #[derive(Default)]
+    struct PerVisitTokenTransport {
+        visits:Mutex<u32>,
+    }
+
+    impl HttpTransport for PerVisitTokenTransport {
+
+        fn send(&self, request:&GmailnatorRequest, body:&str) -> Result<HttpResponse, Error> {
+
+            if request.get_method() == HttpMethod::Get {
+
+                let token = {
+                    let mut visits = self.visits.lock().unwrap();
+                    *visits += 1;
+                    format!("visit{}", visits)
+                };
+
+                std::thread::sleep(std::time::Duration::from_millis(50)); //Lets the other callers reach the landing page too
+
+                return Ok(HttpResponse::new(200, "").with_header("Set-Cookie", &format!("csrf_gmailnator_cookie={}", token)));
+
+            }
+
+            let cookie = request.get_header("Cookie").unwrap_or_default().to_string();
+
+            match body.split('&').next() {
+                Some(field) if cookie.contains(&field.replace("csrf_gmailnator_token=", "csrf_gmailnator_cookie=")) => Ok(HttpResponse::new(200, "fake+abc@gmail.com")),
+                _ => Ok(HttpResponse::new(403, "The action you have requested is not allowed.")),
+            }
+
+        }
+
+    }
+
+    #[test]
+    fn concurrent_callers_share_one_handshake() {
+
+        let transport = Arc::new(PerVisitTokenTransport::default());
+
+        let client = GmailnatorClient::builder().transport(transport.clone()).build().unwrap();
+
+        let handles:Vec<_> = (0..8).map(|_| {
+            let client = client.clone();
+            std::thread::spawn(move || client.new_inbox().map(|_| ()))
+        }).collect();
+
+        for handle in handles {
+            assert!(handle.join().unwrap().is_ok());
+        }
+
+        assert_eq!(*transport.visits.lock().unwrap(), 1);
+
+    }
+
+    #[test]
+    fn blocking_calls_work_inside_an_executor() {
+
+        let transport = FakeTransport::new(vec![("/index/indexquery", HttpResponse::new(200, "fake+abc@gmail.com"))]);
+
+        let client = fake_client(transport);
+
+        let inbox = futures::executor::block_on(async { client.new_inbox() }).unwrap();
+
+        assert_eq!(inbox.get_address(), "fake+abc@gmail.com");
+
+    }
+
+    #[test]
+    fn failed_handshake_is_reported() {
+
+        let transport = FakeTransport::new(vec![("gmailnator.com/", HttpResponse::new(503, ""))]);
+
+        assert!(matches!(fake_client(transport).new_inbox(), Err(Error::ServerError(503))));
+
+    }
+
+}
+
+#[cfg(test)]
+mod client_tests {
+
+    use crate::transport_tests::FakeTransport;
+    use crate::{GmailnatorClient, HttpResponse, Error};
+
+    #[test]
+    fn client_settings_are_applied() {
+
+        let transport = FakeTransport::new(vec![("/index/indexquery", HttpResponse::new(500, ""))]);
+
+        let client = GmailnatorClient::builder()
+                                     .base_url("http://localhost:8080/")
+                                     .user_agent("gmailnator-tests")
+                                     .header("X-Test", "1")
+                                     .max_tries(3)
+                                     .transport(transport.clone())
+                                     .build()
+                                     .unwrap();
+
+        assert!(client.new_inbox().is_err());
+
+        let requests = transport.requests.lock().unwrap();
+
+        assert_eq!(requests.len(), 4);
+        assert_eq!(requests[0].0.get_url(), "http://localhost:8080/");
+        assert_eq!(requests[1].0.get_url(), "http://localhost:8080/index/indexquery");
+        assert_eq!(requests[1].0.get_header("user-agent"), Some("gmailnator-tests"));
+        assert_eq!(requests[1].0.get_header("X-Test"), Some("1"));
+
+    }
+
+    #[test]
+    fn custom_headers_replace_the_default_ones() {
+
+        let transport = FakeTransport::new(vec![
+            ("/index/indexquery", HttpResponse::new(200, "fake+abc@gmail.com")),
+            ("localhost:8080/", HttpResponse::new(200, "").with_header("Set-Cookie", "csrf_gmailnator_cookie=t0k3n")),
+        ]);
+
+        let client = GmailnatorClient::builder()
+                                     .base_url("http://localhost:8080")
+                                     .header("Content-Type", "application/x-www-form-urlencoded")
+                                     .header("Cookie", "consent=yes; csrf_gmailnator_cookie=stale")
+                                     .transport(transport.clone())
+                                     .build()
+                                     .unwrap();
+
+        client.new_inbox().unwrap();
+
+        let requests = transport.requests.lock().unwrap();
+
+        assert_eq!(requests[1].0.get_header("Content-Type"), Some("application/x-www-form-urlencoded"));
+        assert_eq!(requests[1].0.get_header("Cookie"), Some("consent=yes; csrf_gmailnator_cookie=t0k3n;"));
+
+    }
+
+    #[test]
+    fn inboxes_keep_their_client() {
+
+        let transport = FakeTransport::new(vec![("/index/indexquery", HttpResponse::new(200, "fake+abc@gmail.com"))]);
+
+        let client = GmailnatorClient::builder().base_url("http://127.0.0.1").transport(transport).build().unwrap();
+
+        let inbox = client.new_inbox().unwrap();
+
+        assert_eq!(inbox.get_client().get_base_url(), "http://127.0.0.1");
+
+    }
+
+    #[test]
+    fn invalid_client_settings() {
+
+        assert!(matches!(GmailnatorClient::builder().base_url("not an url").build(), Err(Error::ClientConfigError(_))));
+        assert!(matches!(GmailnatorClient::builder().max_tries(0).build(), Err(Error::ClientConfigError(_))));
+        assert!(matches!(GmailnatorClient::builder().proxy("://").build(), Err(Error::ClientConfigError(_))));
+
+    }
+
+}
+
+#[cfg(test)]
+mod bulk_tests {
+
+    use std::collections::HashSet;
+
+    use crate::transport_tests::{FakeTransport, fake_client};
+    use crate::mock::MockServer;
+    use crate::{HttpResponse, Error};
+
+    #[test]
+    fn bulk_beyond_the_limit_is_chunked() {
+
+        let server = MockServer::start().unwrap();
+
+        let mut inboxes = server.client().new_bulk_iter(2500).unwrap();
+
+        let addresses:HashSet<String> = inboxes.by_ref().map(|inbox| inbox.unwrap().get_address().to_string()).collect();
+
+        assert_eq!(addresses.len(), 2500);
+        assert_eq!(inboxes.get_created(), 2500);
+
+        let chunks:Vec<String> = server.get_requests()
+                                       .iter()
+                                       .filter_map(|request| request.get_field("email_list").map(|count| count.to_string()))
+                                       .collect();
+
+        assert_eq!(chunks, vec!["999", "999", "499"]);
+
+    }
+
+    #[test]
+    fn duplicates_are_skipped_and_progress_reported() {
+
+        let html = "<div id=\"email-list-message\"><a>one+a@gmail.com</a><a>ONE+A@gmail.com</a><a>o.n.e+a@googlemail.com</a><a>two+b@gmail.com</a></div>";
+
+        let transport = FakeTransport::new(vec![("/bulk-emails", HttpResponse::new(200, html))]);
+
+        let results:Vec<_> = fake_client(transport).new_bulk_iter(3).unwrap().collect();
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[1].as_ref().unwrap().get_address(), "two+b@gmail.com");
+        assert!(matches!(results[2], Err(Error::BulkCreationError {created:2, requested:3, ..})));
+
+    }
+
+    #[test]
+    fn failed_chunk_ends_the_iteration() {
+
+        let server = MockServer::start().unwrap();
+
+        let mut inboxes = server.client().new_bulk_iter(1500).unwrap();
+
+        assert!(inboxes.next().unwrap().is_ok());
+
+        server.fail_next(502, 2);
+
+        let error = inboxes.by_ref().find_map(|inbox| inbox.err()).unwrap();
+
+        assert!(matches!(error, Error::BulkCreationError {created:1000, requested:1500, ..}));
+        assert!(inboxes.next().is_none());
+        assert!(matches!(server.client().new_bulk_iter(0), Err(Error::InvalidCountError(0))));
+
+    }
+
+}
+
+#[cfg(test)]
+mod address_tests {
+
+    use crate::transport_tests::{FakeTransport, fake_client};
+    use crate::{EmailAddress, GmailnatorInbox, HttpResponse, Error};
+
+    #[test]
+    fn addresses_are_split_in_parts() {
+
+        let plus = EmailAddress::parse("  Ex.Tmp+abcd@gmail.com\n").unwrap();
+
+        assert_eq!(plus.as_str(), "Ex.Tmp+abcd@gmail.com");
+        assert_eq!(plus.get_local_part(), "Ex.Tmp+abcd");
+        assert_eq!(plus.get_tag(), Some("abcd"));
+        assert_eq!(plus.get_server_id(), "extmp");
+
+        let public = EmailAddress::parse("tmp1234@gmailnator.com").unwrap();
+
+        assert_eq!(public.get_tag(), None);
+        assert_eq!(public.get_server_id(), "tmp1234@gmailnator.com");
+
+        for invalid in &["", "no-at-sign", "a@b@gmail.com", "+tag@gmail.com", "abc+@gmail.com", "a b@gmail.com", "abc@localhost", "abc@-gmail.com", "<b>abc</b>@gmail.com"] {
+            assert!(matches!(EmailAddress::parse(invalid), Err(Error::InvalidAddressError {..})), "{} was accepted", invalid);
+        }
+
+    }
+
+    #[test]
+    fn gmail_addresses_are_canonicalized() {
+
+        let parse = |address:&str| EmailAddress::parse(address).unwrap();
+
+        assert_eq!(parse("E.x.Tmp+Tag@GoogleMail.com").get_canonical(), "extmp+tag@gmail.com");
+        assert_eq!(parse("Tmp.1234@GmailNator.com").get_canonical(), "tmp.1234@gmailnator.com");
+
+        assert_eq!(parse("ex.tmp@gmail.com"), parse("EXTMP@googlemail.com"));
+        assert_ne!(parse("extmp+a@gmail.com"), parse("extmp+b@gmail.com"));
+        assert_ne!(parse("ex.tmp@gmailnator.com"), parse("extmp@gmailnator.com"));
+
+        assert!(parse("extmp+a@gmail.com").is_same_mailbox(&parse("e.xtmp+b@googlemail.com")));
+        assert!(!parse("extmp@gmail.com").is_same_mailbox(&parse("extmp2@gmail.com")));
+
+        assert_eq!("extmp+a@gmail.com".parse::<EmailAddress>().unwrap().to_string(), "extmp+a@gmail.com");
+
+    }
+
+    #[test]
+    fn inboxes_keep_their_address_when_serialized() {
+
+        let inbox = GmailnatorInbox::from_address("ex.tmp+abcd@gmail.com").unwrap();
+
+        let json = serde_json::to_string(&inbox).unwrap();
+        let restored:GmailnatorInbox = serde_json::from_str(&json).unwrap();
+
+        assert!(json.contains("\"mail_address\":\"ex.tmp+abcd@gmail.com\""));
+        assert_eq!(restored.get_email_address().get_server_id(), "extmp");
+        assert!(serde_json::from_str::<GmailnatorInbox>(&json.replace("ex.tmp+abcd@gmail.com", "invalid")).is_err());
+
+    }
+
+    #[test]
+    fn invalid_generated_addresses_are_rejected() {
+
+        let transport = FakeTransport::new(vec![("/index/indexquery", HttpResponse::new(200, "<html><body>Something went wrong</body></html>"))]);
+
+        assert!(matches!(fake_client(transport).new_inbox(), Err(Error::MailServerParsingError(_))));
+
+        assert!(matches!(GmailnatorInbox::from_address("not an address"), Err(Error::InvalidAddressError {..})));
+
+    }
+
+}
+
+#[cfg(test)]
+mod address_kind_tests {
+
+    use crate::mock::{MockServer, MockMessage};
+    use crate::AddressKind;
+
+    #[test]
+    fn inboxes_are_created_with_the_requested_kind() {
+
+        let server = MockServer::start().unwrap();
+        let client = server.client();
+
+        let public = client.new_inbox_with_kind(AddressKind::PublicDomain).unwrap();
+        let dotted = client.new_bulk_with_kind(2, AddressKind::DotVariant).unwrap();
+
+        assert_eq!(public.get_kind(), AddressKind::PublicDomain);
+        assert!(public.get_address().ends_with("@gmailnator.com"));
+        assert!(dotted.iter().all(|inbox| inbox.get_kind() == AddressKind::DotVariant && !inbox.get_address().contains('+')));
+        assert_eq!(client.new_inbox().unwrap().get_kind(), AddressKind::PlusAlias);
+
+        let requests = server.get_requests();
+        let posts:Vec<_> = requests.iter().filter(|request| request.method == "POST").collect();
+
+        assert_eq!(posts[0].get_field("data[]"), Some("1"));
+        assert_eq!(posts[1].get_field("email[]"), Some("3"));
+        assert_eq!(posts[2].get_field("data[]"), Some("2"));
+
+        server.inject_message(dotted[0].get_address(), MockMessage::new("Dotted", ""));
+        assert_eq!(dotted[0].get_messages_iter().unwrap().count(), 1);
+
+    }
+
+    #[test]
+    fn kind_is_guessed_from_existing_addresses() {
+
+        assert_eq!(AddressKind::of_address("extmp+abc@gmail.com"), AddressKind::PlusAlias);
+        assert_eq!(AddressKind::of_address("e.x.tmp@GoogleMail.com"), AddressKind::DotVariant);
+        assert_eq!(AddressKind::of_address("tmp1234@gmailnator.com"), AddressKind::PublicDomain);
+
+    }
+
+    #[test]
+    fn messages_are_fetched_with_the_address_before_its_tag() {
+
+        let server = MockServer::start().unwrap();
+        let client = server.client();
+
+        for (address, server_id) in [("Ab.Cd+abcd@gmail.com", "Ab.Cd"), ("Ex.Tmp@gmail.com", "Ex.Tmp@gmail.com"), ("tmp1234@gmailnator.com", "tmp1234@gmailnator.com")] {
+
+            let inbox = client.inbox_from_address(address).unwrap();
+
+            server.inject_message(address, MockMessage::new("Hello", ""));
+            assert_eq!(inbox.get_messages_iter().unwrap().count(), 1);
+
+            let requests = server.get_requests();
+            let fetch = requests.iter().rev().find(|request| request.get_field("action") == Some("get_message")).unwrap();
+
+            assert_eq!(fetch.get_field("email"), Some(server_id));
+
+        }
+
+    }
+
+}
+
+#[cfg(test)]
+mod alias_tests {
+
+    use crate::mock::{MockServer, MockMessage};
+    use crate::{GmailnatorInbox, Error};
+
+    #[test]
+    fn aliases_are_expanded_offline() {
+
+        let inbox = GmailnatorInbox::from_address("abc+xyz@gmail.com").unwrap();
+
+        let aliases:Vec<String> = inbox.aliases().map(|alias| alias.to_string()).collect();
+
+        assert_eq!(aliases, vec!["abc@gmail.com", "a.bc@gmail.com", "ab.c@gmail.com", "a.b.c@gmail.com",
+                                 "abc@googlemail.com", "a.bc@googlemail.com", "ab.c@googlemail.com", "a.b.c@googlemail.com"]);
+
+        assert_eq!(inbox.alias_with_tag("shop").unwrap().to_string(), "abc+shop@gmail.com");
+        assert!(matches!(inbox.alias_with_tag("no spaces"), Err(Error::InvalidAddressError {..})));
+
+        let public = GmailnatorInbox::from_address("tmp1234@gmailnator.com").unwrap();
+
+        assert_eq!(public.aliases().count(), 0);
+        assert!(matches!(public.alias_with_tag("shop"), Err(Error::InvalidAddressError {..})));
+
+    }
+
+    #[test]
+    fn messages_are_traced_back_to_their_alias() {
+
+        let server = MockServer::start().unwrap();
+        let inbox = server.client().new_inbox().unwrap();
+
+        let alias = inbox.alias_with_tag("newsletter").unwrap();
+        let dotted = inbox.aliases().nth(1).unwrap();
+
+        server.inject_message(alias.as_str(), MockMessage::new("Weekly news", "<p>News</p>"));
+        server.inject_message(dotted.as_str(), MockMessage::new("Welcome", "<p>Hello</p>"));
+        server.inject_message("someone.else@gmail.com", MockMessage::new("Not for us", ""));
+
+        let messages:Vec<_> = inbox.get_messages_iter().unwrap().collect();
+        let news = messages.iter().find(|message| message.get_subject() == "Weekly news").unwrap();
+        let welcome = messages.iter().find(|message| message.get_subject() == "Welcome").unwrap();
+
+        assert_eq!(messages.len(), 2);
+        assert_eq!(news.get_recipient(), Some(alias.as_str()));
+        assert_eq!(inbox.get_message_alias(news).unwrap().get_tag(), Some("newsletter"));
+        assert_eq!(inbox.get_message_alias(welcome), Some(dotted));
+
+        let other = GmailnatorInbox::from_address("other@gmail.com").unwrap();
+
+        assert_eq!(other.get_message_alias(news), None);
+
+    }
+
+}
+
+#[cfg(test)]
+mod watch_tests {
+
+    use crate::mock::{MockServer, MockMessage, MockRequest};
+    use crate::{GmailnatorInbox, WatchSnapshot, RetryPolicy, Error};
+
+    fn count_fetched(requests:&[MockRequest]) -> usize {
+        requests.iter().filter(|request| request.path.ends_with("/get_single_message")).count()
+    }
+
+    #[test]
+    fn only_new_messages_are_fetched() {
+
+        let server = MockServer::start().unwrap();
+        let inbox = server.client().new_inbox().unwrap();
+
+        server.inject_message(inbox.get_address(), MockMessage::new("First", ""));
+
+        let mut watcher = inbox.watch();
+
+        assert_eq!(watcher.poll().unwrap().len(), 1);
+        assert!(watcher.poll().unwrap().is_empty());
+
+        server.inject_message(inbox.get_address(), MockMessage::new("Second", ""));
+
+        let new_messages = watcher.poll().unwrap();
+
+        assert_eq!(new_messages.len(), 1);
+        assert_eq!(new_messages[0].get_subject(), "Second");
+        assert_eq!(watcher.get_seen_count(), 2);
+        assert_eq!(count_fetched(&server.get_requests()), 2);
+
+    }
+
+    #[test]
+    fn failed_messages_are_retried_then_given_up() {
+
+        let server = MockServer::start().unwrap();
+        let client = server.client_builder().retry_policy(RetryPolicy::never()).build().unwrap();
+        let inbox = client.new_inbox().unwrap();
+
+        let first = server.inject_message(inbox.get_address(), MockMessage::new("First", ""));
+        let second = server.inject_message(inbox.get_address(), MockMessage::new("Second", ""));
+
+        let mut watcher = inbox.watch();
+
+        //The first message of the list can't be retrieved, the ones after it are still returned
+        let list = format!(r#"[{{"content":"<a href=\"/x/messageid/#unknown\"></a>"}},{{"content":"<a href=\"/x/messageid/#{}\"></a>"}},{{"content":"<a href=\"/x/messageid/#{}\"></a>"}}]"#, first, second);
+
+        server.respond_next(200, &[], &list);
+
+        let results = watcher.try_poll().unwrap();
+
+        assert_eq!(results.len(), 3);
+        assert!(matches!(results[0], Err(Error::ServerError(500))));
+        assert!(watcher.is_seen(&first) && watcher.is_seen(&second));
+        assert!(!watcher.is_seen("unknown"));
+
+        server.respond_next(200, &[], &list);
+        assert!(watcher.poll().unwrap().is_empty());
+
+        server.respond_next(200, &[], &list);
+        assert_eq!(watcher.try_poll().unwrap().len(), 1);
+
+        //Given up after failing in 3 polls
+        assert!(watcher.is_seen("unknown"));
+
+        server.respond_next(200, &[], &list);
+        assert!(watcher.try_poll().unwrap().is_empty());
+
+        //Only the mailbox list fails a poll
+        server.respond_next(500, &[], "");
+        assert!(watcher.try_poll().is_err());
+
+    }
+
+    #[test]
+    fn snapshots_resume_watching() {
+
+        let server = MockServer::start().unwrap();
+        let inbox = server.client().new_inbox().unwrap();
+
+        let old_id = server.inject_message(inbox.get_address(), MockMessage::new("Old", ""));
+
+        let mut watcher = inbox.watch();
+        watcher.skip_existing().unwrap();
+
+        assert!(watcher.is_seen(&old_id));
+        assert_eq!(count_fetched(&server.get_requests()), 0);
+
+        let json = serde_json::to_string(&watcher.get_snapshot()).unwrap();
+        let snapshot:WatchSnapshot = serde_json::from_str(&json).unwrap();
+
+        server.inject_message(inbox.get_address(), MockMessage::new("New", ""));
+
+        let mut resumed = inbox.resume_watch(&snapshot);
+        let new_messages = resumed.poll().unwrap();
+
+        assert_eq!(new_messages.len(), 1);
+        assert_eq!(new_messages[0].get_subject(), "New");
+
+        let other = GmailnatorInbox::from_address("other@gmail.com").unwrap();
+
+        assert_eq!(other.resume_watch(&snapshot).get_seen_count(), 0);
+
+    }
+
+}
+
+#[cfg(test)]
+mod multi_watch_tests {
+
+    use std::collections::HashSet;
+    use std::time::{Duration, Instant};
+
+    use crate::mock::{MockServer, MockMessage};
+    use crate::{MultiInboxWatcher, Error};
+
+    #[test]
+    fn messages_of_every_inbox_are_merged() {
+
+        let server = MockServer::start().unwrap();
+        let inboxes = server.client().new_bulk(5).unwrap();
+
+        let watcher = MultiInboxWatcher::builder()
+                                        .concurrency(2)
+                                        .poll_interval(Duration::from_millis(50))
+                                        .rate_limit(Duration::from_millis(5))
+                                        .build()
+                                        .unwrap();
+
+        for inbox in &inboxes {
+            assert!(watcher.add(inbox));
+            server.inject_message(inbox.get_address(), MockMessage::new(inbox.get_address(), ""));
+        }
+
+        assert!(!watcher.add(&inboxes[0]));
+        assert_eq!(watcher.len(), 5);
+
+        let mut received = HashSet::new();
+
+        while received.len() < 5 {
+
+            let (address, message) = watcher.recv_timeout(Duration::from_secs(5)).expect("A message was not received.");
+
+            assert_eq!(message.get_subject(), address);
+            assert!(received.insert(address), "A message was received twice.");
+
+        }
+
+        //Inboxes removed while running are not polled anymore, added ones are
+        assert!(watcher.remove(inboxes[0].get_address()));
+        assert!(!watcher.remove(inboxes[0].get_address()));
+
+        let added = server.client().new_inbox().unwrap();
+        watcher.add(&added);
+
+        server.inject_message(inboxes[0].get_address(), MockMessage::new("Removed", ""));
+        server.inject_message(added.get_address(), MockMessage::new("Added", ""));
+
+        let (address, message) = watcher.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        assert_eq!(address, added.get_address());
+        assert_eq!(message.get_subject(), "Added");
+        assert!(watcher.recv_timeout(Duration::from_millis(200)).is_none());
+
+    }
+
+    #[test]
+    fn failed_messages_do_not_hold_back_the_others() {
+
+        let server = MockServer::start().unwrap();
+        let inbox = server.client().new_inbox().unwrap();
+
+        let id = server.inject_message(inbox.get_address(), MockMessage::new("Hello", ""));
+
+        let list = format!(r#"[{{"content":"<a href=\"/x/messageid/#unknown\"></a>"}},{{"content":"<a href=\"/x/messageid/#{}\"></a>"}}]"#, id);
+        server.respond_next(200, &[], &list);
+
+        let watcher = MultiInboxWatcher::builder().poll_interval(Duration::from_secs(60)).build().unwrap();
+
+        watcher.add(&inbox);
+
+        let (_, message) = watcher.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        assert_eq!(message.get_subject(), "Hello");
+
+        let errors = watcher.take_errors();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, inbox.get_address());
+
+    }
+
+    #[test]
+    fn dropping_does_not_wait_for_the_workers() {
+
+        let server = MockServer::start().unwrap();
+        let inboxes = server.client().new_bulk(2).unwrap();
+
+        let watcher = MultiInboxWatcher::builder()
+                                        .concurrency(2)
+                                        .rate_limit(Duration::from_secs(60))
+                                        .build()
+                                        .unwrap();
+
+        for inbox in &inboxes {
+            server.inject_message(inbox.get_address(), MockMessage::new("Hello", ""));
+            watcher.add(inbox);
+        }
+
+        //The second worker now waits a minute for its turn
+        watcher.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        let start = Instant::now();
+
+        drop(watcher);
+
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+    }
+
+    #[test]
+    fn polls_share_the_rate_limit() {
+
+        let server = MockServer::start().unwrap();
+        let inboxes = server.client().new_bulk(4).unwrap();
+
+        let watcher = MultiInboxWatcher::builder()
+                                        .concurrency(4)
+                                        .poll_interval(Duration::from_secs(60))
+                                        .rate_limit(Duration::from_millis(100))
+                                        .build()
+                                        .unwrap();
+
+        let start = Instant::now();
+
+        for inbox in &inboxes {
+            server.inject_message(inbox.get_address(), MockMessage::new("Hello", ""));
+            watcher.add(inbox);
+        }
+
+        for _ in 0..4 {
+            watcher.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert!(watcher.take_errors().is_empty());
+
+        assert!(matches!(MultiInboxWatcher::builder().concurrency(0).build(), Err(Error::ClientConfigError(_))));
+
+    }
+
+}
+
+#[cfg(test)]
+mod text_tests {
+
+    use crate::MailMessage;
+
+    fn render(html:&str) -> String {
+        MailMessage::new("Subject".to_string(), html.to_string()).text()
+    }
+
+    #[test]
+    fn blocks_are_put_on_their_own_lines() {
+
+        let html = r#"<html><head><title>Mail</title><style>p { color: red; }</style></head>
+                      <body><script>track();</script><h1>Welcome&nbsp;aboard</h1>
+                      <p>Thanks for   signing
+                      up.<br>Your code is <b>1234</b>.</p><div>Regards,</div><div>The &amp; team</div></body></html>"#;
+
+        assert_eq!(render(html), "Welcome aboard\n\nThanks for signing up.\nYour code is 1234.\n\nRegards,\nThe & team");
+
+    }
+
+    #[test]
+    fn lists_and_tables_are_laid_out() {
+
+        let html = "<p>Steps :</p><ol><li>Open</li><li>Click<ul><li>here</li><li>there</li></ul></li></ol>\
+                    <table><tr><th>Item</th><th>Price</th></tr><tr><td>Book</td><td></td><td>10 $</td></tr></table>\
+                    <pre>line 1\n  line 2</pre>";
+
+        assert_eq!(render(html), "Steps :\n\n1. Open\n2. Click\n  - here\n  - there\n\nItem | Price\nBook | 10 $\n\nline 1\n  line 2");
+
+    }
+
+}
+
+#[cfg(test)]
+mod link_tests {
+
+    use crate::MailMessage;
+
+    #[test]
+    fn links_are_decoded_and_unwrapped() {
+
+        let html = r##"<p>Welcome !</p>
+                      <a href="https://www.google.com/url?q=https%3A%2F%2Fexample.com%2Fconfirm%3Ftoken%3Dabc%26user%3D1&amp;sa=D">
+                          Confirm   your account
+                      </a>
+                      <a href="https://nam02.safelinks.protection.outlook.com/?url=https%3A%2F%2Fwww.google.com%2Furl%3Fq%3Dhttps%253A%252F%252Fshop.example.org%252F&amp;data=x"><img alt="Shop" src="logo.png"></a>
+                      <a href="https://example.com/login?redirect=https%3A%2F%2Fexample.com%2Fhome&amp;amp;lang=en">Log in</a>
+                      <a href="mailto:support@example.com">Support</a><a href="#top">Top</a>"##;
+
+        let message = MailMessage::new("Welcome".to_string(), html.to_string());
+        let links = message.links();
+
+        assert_eq!(links.len(), 4);
+
+        assert_eq!(links[0].get_url(), "https://example.com/confirm?token=abc&user=1");
+        assert_eq!(links[0].get_text(), "Confirm your account");
+        assert!(links[0].get_raw_url().starts_with("https://www.google.com/url?q="));
+
+        assert_eq!(links[1].get_url(), "https://shop.example.org/");
+        assert_eq!(links[1].get_text(), "Shop");
+
+        assert_eq!(links[2].get_url(), "https://example.com/login?redirect=https%3A%2F%2Fexample.com%2Fhome&lang=en");
+        assert_eq!(links[3].get_domain(), None);
+
+        assert_eq!(message.find_link_by_text("confirm").unwrap().get_domain().as_deref(), Some("example.com"));
+        assert_eq!(message.find_link_by_domain("example.org").unwrap().get_text(), "Shop");
+        assert_eq!(message.find_link_by_domain("ample.org"), None);
+        assert_eq!(message.find_link_by_text("unsubscribe"), None);
+
+    }
+
+    #[test]
+    fn confirmation_links_keep_their_token() {
+
+        let html = r#"<a href="https://app.example.com/auth/magic/link?token=SECRET&amp;redirect=https://app.example.com/home">Sign in</a>
+                      <a href="https://mail.example.com/verify/click?t=abc&amp;url=https%3A%2F%2Fexample.com%2Fwelcome">Verify</a>
+                      <a href="https://l.facebook.com/l.php?u=https%3A%2F%2Fexample.com%2Fevent&amp;h=AT0">Event</a>"#;
+
+        let links = MailMessage::new("Sign in".to_string(), html.to_string()).links();
+
+        assert_eq!(links[0].get_url(), "https://app.example.com/auth/magic/link?token=SECRET&redirect=https://app.example.com/home");
+        assert_eq!(links[1].get_url(), "https://mail.example.com/verify/click?t=abc&url=https%3A%2F%2Fexample.com%2Fwelcome");
+        assert_eq!(links[2].get_url(), "https://example.com/event");
+
+    }
+
+}
+
+#[cfg(test)]
+mod code_tests {
+
+    use std::time::Duration;
+
+    use crate::mock::{MockServer, MockMessage};
+    use crate::{MailMessage, CodeExtractor, Error};
+
+    #[test]
+    fn codes_are_ranked_by_keyword_proximity() {
+
+        let html = "<p>Order #55512 shipped on 2024-05-01, call 555-1234 for help.</p>\
+                    <p>Your verification code is <b>483920</b>.</p><p>Reference : 7777</p>";
+
+        let message = MailMessage::new("Sign in to Example".to_string(), html.to_string());
+
+        assert_eq!(message.extract_codes(), vec!["483920", "7777", "55512"]);
+        assert_eq!(message.extract_code().as_deref(), Some("483920"));
+
+        let subject_only = MailMessage::new("1234 is your login code".to_string(), "<p>Welcome</p>".to_string());
+
+        assert_eq!(subject_only.extract_codes(), vec!["1234"]);
+
+    }
+
+    #[test]
+    fn custom_patterns_are_used() {
+
+        let extractor = CodeExtractor::with_patterns(&[r"\b[A-Z0-9]{3}-[A-Z0-9]{3}\b"]).unwrap();
+
+        assert_eq!(extractor.extract("Use G7K-Q2Z as your code, not 123456"), vec!["G7K-Q2Z"]);
+        assert!(matches!(CodeExtractor::with_patterns(&["(unclosed"]), Err(Error::InvalidPatternError {..})));
+
+    }
+
+    #[test]
+    fn wait_flows_return_the_code() {
+
+        let server = MockServer::start().unwrap();
+        let inbox = server.client().new_inbox().unwrap();
+
+        server.inject_message(inbox.get_address(), MockMessage::new("Welcome", "<p>No code here</p>"));
+        server.inject_message(inbox.get_address(), MockMessage::new("Your code", "<p>Enter 902113 to continue</p>"));
+
+        let code = inbox.wait_for_code(|_| true, Duration::from_secs(1), Duration::from_millis(10)).unwrap();
+
+        assert_eq!(code, "902113");
+
+        let result = inbox.wait_for_code(|message| message.get_subject() == "Welcome", Duration::from_millis(50), Duration::from_millis(10));
+
+        assert!(matches!(result, Err(Error::WaitTimeoutError(_))));
+
+    }
+
+}
+
+#[cfg(test)]
+mod export_tests {
+
+    use crate::mock::{MockServer, MockMessage};
+    use crate::MailMessage;
+
+    #[test]
+    fn messages_are_exported_as_eml() {
+
+        let message = MailMessage::new("Bienvenue à bord".to_string(), "<p>Hello</p>".to_string());
+        let eml = message.to_eml();
+
+        let (headers, body) = eml.split_once("\r\n\r\n").unwrap();
+
+        assert!(headers.contains("Subject: =?UTF-8?B?QmllbnZlbnVlIMOgIGJvcmQ=?="));
+        assert!(headers.contains("From: unknown@gmailnator.invalid"));
+        assert!(headers.contains("Content-Type: text/html; charset=utf-8\r\nContent-Transfer-Encoding: base64"));
+        assert!(headers.lines().any(|line| line.starts_with("Date: ")));
+        assert!(headers.lines().any(|line| line.starts_with("Message-ID: <") && line.ends_with("@gmailnator.com>")));
+        assert!(!headers.contains("To: "));
+        assert_eq!(body, "PHA+SGVsbG88L3A+\r\n");
+
+        //Messages without an id are keyed by their content
+        let other = MailMessage::new("Bienvenue à bord".to_string(), "<p>Bye</p>".to_string());
+
+        assert_eq!(message.get_file_key().len(), 16);
+        assert_ne!(message.get_file_key(), other.get_file_key());
+
+    }
+
+    #[test]
+    fn inboxes_are_exported_as_mbox() {
+
+        let server = MockServer::start().unwrap();
+        let inbox = server.client().new_inbox().unwrap();
+
+        let first_id = server.inject_message(inbox.get_address(), MockMessage::new("First", "<p>One</p>").with_sender("\"Example\" <news@example.com>"));
+        server.inject_message(inbox.get_address(), MockMessage::new("Second", &"<p>Two</p>".repeat(20)));
+
+        let mut mbox = Vec::new();
+
+        assert_eq!(inbox.export_mbox(&mut mbox).unwrap(), 2);
+
+        let mbox = String::from_utf8(mbox).unwrap();
+        let separators:Vec<&str> = mbox.lines().filter(|line| line.starts_with("From ")).collect();
+
+        assert_eq!(separators.len(), 2);
+        assert!(separators.iter().any(|line| line.starts_with("From news@example.com ")));
+        assert!(mbox.contains("From: Example <news@example.com>\n"));
+        assert!(mbox.contains(&format!("To: {}\n", inbox.get_address())));
+        assert!(mbox.contains(&format!("Message-ID: <{}@gmailnator.com>\n", first_id)));
+        assert!(!mbox.contains('\r'));
+
+    }
+
+    #[test]
+    fn maildir_syncs_are_idempotent() {
+
+        let server = MockServer::start().unwrap();
+        let inbox = server.client().new_inbox().unwrap();
+
+        let maildir = std::env::temp_dir().join(format!("gmailnator-maildir-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&maildir);
+
+        let first_id = server.inject_message(inbox.get_address(), MockMessage::new("First", "<p>One</p>"));
+
+        assert_eq!(inbox.sync_to_maildir(&maildir).unwrap(), 1);
+        assert_eq!(inbox.sync_to_maildir(&maildir).unwrap(), 0);
+
+        //A mail client reading the message moves it to cur with its flags
+        let name = format!("{}.gmailnator", first_id);
+        std::fs::rename(maildir.join("new").join(&name), maildir.join("cur").join(format!("{}:2,S", name))).unwrap();
+
+        let second_id = server.inject_message(inbox.get_address(), MockMessage::new("Second", "<p>Two</p>"));
+
+        assert_eq!(inbox.sync_to_maildir(&maildir).unwrap(), 1);
+
+        let fetched = server.get_requests().iter().filter(|request| request.path.ends_with("/get_single_message")).count();
+        let stored = std::fs::read_to_string(maildir.join("new").join(format!("{}.gmailnator", second_id))).unwrap();
+
+        assert_eq!(fetched, 2);
+        assert!(stored.contains("Subject: Second\r\n"));
+        assert!(std::fs::read_dir(maildir.join("tmp")).unwrap().next().is_none());
+
+        std::fs::remove_dir_all(&maildir).unwrap();
+
+    }
+
+}
+
+#[cfg(test)]
+mod retry_tests {
+
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use crate::{GmailnatorClient, GmailnatorRequest, HttpTransport, HttpResponse, HttpMethod, RetryPolicy, Error};
+
+    /// Answers the POST requests with the queued outcomes, then with an address.
+    struct SequenceTransport {
+        outcomes:Mutex<VecDeque<Result<HttpResponse, Error>>>,
+        posts:Mutex<u32>,
+    }
+
+    impl HttpTransport for SequenceTransport {
+
+        fn send(&self, request:&GmailnatorRequest, _body:&str) -> Result<HttpResponse, Error> {
+
+            if request.get_method() == HttpMethod::Get {
+                return Ok(HttpResponse::new(200, ""));
+            }
+
+            *self.posts.lock().unwrap() += 1;
+
+            self.outcomes.lock().unwrap().pop_front().unwrap_or_else(|| Ok(HttpResponse::new(200, "fake+abc@gmail.com")))
+
+        }
+
+    }
+
+    fn client_with(outcomes:Vec<Result<HttpResponse, Error>>, policy:RetryPolicy) -> (GmailnatorClient, Arc<SequenceTransport>) {
+
+        let transport = Arc::new(SequenceTransport {outcomes:Mutex::new(outcomes.into()), posts:Mutex::new(0)});
+
+        let client = GmailnatorClient::builder().transport(transport.clone()).retry_policy(policy).build().unwrap();
+
+        (client, transport)
+
+    }
+
+    #[test]
+    fn retryable_statuses_are_retried_after_retry_after() {
+
+        let policy = RetryPolicy::new().with_max_attempts(3).with_backoff(Duration::from_millis(400), Duration::from_secs(10));
+
+        let (client, transport) = client_with(vec![
+            Ok(HttpResponse::new(503, "")),
+            Ok(HttpResponse::new(429, "").with_header("Retry-After", "0")),
+        ], policy.with_retry_after(true, Duration::from_secs(1)));
+
+        let start = Instant::now();
+
+        assert!(client.new_inbox().is_ok());
+        assert_eq!(*transport.posts.lock().unwrap(), 3);
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert!(start.elapsed() < Duration::from_millis(800)); //The backoff would have waited at least 400ms more without the Retry-After header
+
+    }
+
+    #[test]
+    fn non_retryable_failures_are_returned_at_once() {
+
+        let policy = RetryPolicy::new().with_max_attempts(5).with_backoff(Duration::from_millis(0), Duration::from_millis(0));
+
+        let (client, transport) = client_with(vec![Ok(HttpResponse::new(404, ""))], policy.clone());
+
+        assert!(matches!(client.new_inbox(), Err(Error::ServerError(404))));
+        assert_eq!(*transport.posts.lock().unwrap(), 1);
+
+        let (client, transport) = client_with(vec![Err(Error::ConnectionError("reset".into()))], policy.clone().with_transport_errors_retried(false));
+
+        assert!(client.new_inbox().is_err());
+        assert_eq!(*transport.posts.lock().unwrap(), 1);
+
+        let (client, transport) = client_with(vec![Ok(HttpResponse::new(503, "").with_header("Retry-After", "3600"))], policy);
+
+        assert!(matches!(client.new_inbox(), Err(Error::ServerError(503))));
+        assert_eq!(*transport.posts.lock().unwrap(), 1);
+
+    }
+
+    #[test]
+    fn backoff_grows_exponentially() {
+
+        let policy = RetryPolicy::new().with_max_attempts(10).with_jitter(false).with_backoff(Duration::from_millis(100), Duration::from_secs(1));
+
+        assert_eq!(policy.get_delay(1, None), Some(Duration::from_millis(100)));
+        assert_eq!(policy.get_delay(3, None), Some(Duration::from_millis(400)));
+        assert_eq!(policy.get_delay(6, None), Some(Duration::from_secs(1)));
+        assert_eq!(policy.get_delay(10, None), None);
+
+        let jittered = RetryPolicy::new().with_backoff(Duration::from_millis(100), Duration::from_secs(1)).get_delay(1, None).unwrap();
+
+        assert!(jittered >= Duration::from_millis(50) && jittered <= Duration::from_millis(100));
+
+    }
+
+    #[test]
+    fn retry_after_values_are_parsed() {
+
+        use crate::retry::parse_retry_after;
+        use chrono::{TimeZone, Utc};
+
+        let now = Utc.with_ymd_and_hms(2015, 10, 21, 7, 28, 0).unwrap();
+
+        assert_eq!(parse_retry_after("120", now), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now), Some(Duration::from_secs(30)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:27:00 GMT", now), Some(Duration::from_secs(0)));
+        assert_eq!(parse_retry_after("soon", now), None);
+
+    }
+
+}
+
+#[cfg(test)]
+mod blocking_tests {
+
+    use std::time::Duration;
+
+    use crate::mock::MockServer;
+    use crate::transport_tests::{FakeTransport, fake_client};
+    use crate::{HttpResponse, RetryPolicy, Error};
+
+    const CHALLENGE_PAGE:&str = "<!DOCTYPE html><html><head><title>Just a moment...</title></head><body><script>window._cf_chl_opt={};</script></body></html>";
+    const CAPTCHA_PAGE:&str = "<html><body><form><div class=\"g-recaptcha\" data-sitekey=\"key\"></div></form></body></html>";
+
+    #[test]
+    fn rate_limits_keep_their_retry_after_hint() {
+
+        let server = MockServer::start().unwrap();
+
+        let client = server.client_builder().retry_policy(RetryPolicy::never()).build().unwrap();
+
+        server.respond_next(429, &[("Retry-After", "30")], "Too many requests");
+
+        let error = client.new_inbox().unwrap_err();
+
+        assert!(matches!(error, Error::RateLimitError(Some(_))));
+        assert_eq!(error.get_retry_after(), Some(Duration::from_secs(30)));
+
+    }
+
+    #[test]
+    fn challenges_served_with_200_are_detected() {
+
+        let server = MockServer::start().unwrap();
+        let client = server.client();
+
+        server.respond_next(200, &[], CHALLENGE_PAGE);
+        assert!(matches!(client.new_inbox(), Err(Error::ChallengeError(None))));
+
+        server.respond_next(200, &[], CAPTCHA_PAGE);
+        assert!(matches!(client.new_bulk(2), Err(Error::CaptchaError(None))));
+
+        server.respond_next(403, &[("cf-mitigated", "challenge")], "");
+        let error = client.new_inbox().unwrap_err();
+        assert!(matches!(error, Error::ChallengeError(_)) && error.is_blocking_error());
+
+        //Challenges are not retried
+        let endpoint_requests = server.get_requests().iter().filter(|request| request.method == "POST").count();
+        assert_eq!(endpoint_requests, 3);
+
+    }
+
+    #[test]
+    fn bulk_pages_embedding_a_captcha_are_parsed() {
+
+        let page = "<!DOCTYPE html><html><head><script src=\"https://www.google.com/recaptcha/api.js\"></script></head>\
+                    <body><div id=\"email-list-message\"><a>one+a@gmail.com</a><a>two+b@gmail.com</a></div></body></html>";
+
+        let transport = FakeTransport::new(vec![("/bulk-emails", HttpResponse::new(200, page))]);
+
+        let inboxes = fake_client(transport).new_bulk(2).unwrap();
+
+        assert_eq!(inboxes.len(), 2);
+        assert_eq!(inboxes[0].get_address(), "one+a@gmail.com");
+
+    }
+
+    #[test]
+    fn pages_embedding_cloudflare_scripts_are_parsed() {
+
+        let script = "<script src=\"/cdn-cgi/challenge-platform/scripts/jsd/main.js\"></script>";
+
+        let landing_page = format!("<!DOCTYPE html><html><head>{}</head><body><form><input type=\"hidden\" name=\"csrf_gmailnator_token\" value=\"t0k3n\"></form></body></html>", script);
+        let bulk_page = format!("<!DOCTYPE html><html><head>{}</head><body><div id=\"email-list-message\"><a>one+a@gmail.com</a></div></body></html>", script);
+
+        let transport = FakeTransport::new(vec![
+            ("gmailnator.com/", HttpResponse::new(200, &landing_page)),
+            ("/bulk-emails", HttpResponse::new(200, &bulk_page)),
+        ]);
+
+        let inboxes = fake_client(transport.clone()).new_bulk(1).unwrap();
+
+        assert_eq!(inboxes[0].get_address(), "one+a@gmail.com");
+        assert!(transport.payload(0).starts_with("csrf_gmailnator_token=t0k3n&"));
+
+    }
+
+    #[test]
+    fn message_contents_are_not_mistaken_for_challenges() {
+
+        let message = r#"{"subject":"<b>Verify<\/b>","content":"<html><body><div class=\"g-recaptcha\"><\/div>Just a moment...<\/body><\/html>"}"#;
+
+        let transport = FakeTransport::new(vec![
+            ("gmailnator.com/", HttpResponse::new(200, CAPTCHA_PAGE)),
+            ("/mailbox/mailboxquery", HttpResponse::new(200, crate::transport_tests::MAILBOX_LIST)),
+            ("/mailbox/get_single_message", HttpResponse::new(200, message)),
+        ]);
+
+        let inbox = fake_client(transport).inbox_from_address("fake+abc@gmail.com").unwrap();
+
+        let messages:Vec<_> = inbox.get_messages_try_iter().unwrap().collect();
+
+        assert_eq!(messages[0].as_ref().unwrap().get_subject(), "Verify");
+
+    }
+
+}
+
+#[cfg(all(test, feature = "async"))]
+mod async_tests {
+
+    use std::sync::Arc;
+
+    use futures::executor::block_on;
+    use futures::StreamExt;
+
+    use crate::transport_tests::{FakeTransport, MAILBOX_LIST, SINGLE_MESSAGE, fake_client};
+    use crate::{GmailnatorClient, HttpResponse, PooledTransport, MailMessage};
+
+    #[test]
+    fn create_inbox_async() {
+
+        let transport = FakeTransport::new(vec![("/index/indexquery", HttpResponse::new(200, "fake+abc@gmail.com"))]);
+
+        let client = GmailnatorClient::builder()
+                                     .async_transport(Arc::new(PooledTransport::new(transport, 2)))
+                                     .build()
+                                     .unwrap();
+
+        let inbox = block_on(client.new_inbox_async()).unwrap();
+
+        assert_eq!(inbox.get_address(), "fake+abc@gmail.com");
+
+    }
+
+    #[test]
+    fn stream_messages_async() {
+
+        let transport = FakeTransport::new(vec![
+            ("/mailbox/mailboxquery", HttpResponse::new(200, MAILBOX_LIST)),
+            ("/mailbox/get_single_message", HttpResponse::new(200, SINGLE_MESSAGE)),
+        ]);
+
+        let inbox = fake_client(transport).inbox_from_address("fake+abc@gmail.com").unwrap();
+
+        let messages:Vec<MailMessage> = block_on(async { inbox.get_messages_stream().await.unwrap().collect().await });
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].get_subject(), "Hello");
+
+    }
+
+    #[test]
+    fn delays_share_one_timer_thread() {
+
+        use std::sync::Mutex;
+        use std::time::{Duration, Instant};
+
+        let woken = Arc::new(Mutex::new(Vec::new()));
+
+        //A long delay scheduled first must not hold back the shorter ones scheduled after it
+        let delays = [300u64, 20, 100].iter().chain([50u64; 200].iter()).enumerate().map(|(index, &millis)| {
+
+            let woken = Arc::clone(&woken);
+
+            async move {
+                crate::async_api::delay(Duration::from_millis(millis)).await;
+                woken.lock().unwrap().push(index);
+            }
+
+        });
+
+        let start = Instant::now();
+
+        block_on(futures::future::join_all(delays));
+
+        let woken = woken.lock().unwrap();
+
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert_eq!(woken.len(), 203);
+        assert_eq!(woken[0], 1);
+        assert_eq!(&woken[201..], &[2, 0]);
+
+        #[cfg(target_os = "linux")]
+        {
+            //Thread names are truncated to 15 bytes
+            let timers = std::fs::read_dir("/proc/self/task").unwrap()
+                                                             .filter_map(|task| std::fs::read_to_string(task.unwrap().path().join("comm")).ok())
+                                                             .filter(|name| name.trim() == "gmailnator-time")
+                                                             .count();
+
+            assert_eq!(timers, 1);
+        }
+
+    }
+
+    #[test]
+    fn stream_bulk_async() {
+
+        let server = crate::mock::MockServer::start().unwrap();
+
+        let inboxes:Vec<_> = block_on(server.client().new_bulk_stream(1200).unwrap().collect());
+
+        assert_eq!(inboxes.len(), 1200);
+        assert!(inboxes.iter().all(|inbox| inbox.is_ok()));
+
+    }
+
+}
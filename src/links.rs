@@ -0,0 +1,190 @@
+use scraper::{Html, Selector};
+use htmlescape::decode_html;
+use serde::{Serialize, Deserialize};
+use url::Url;
+
+lazy_static! {
+
+    static ref LINK_SELECTOR:Selector = Selector::parse("a[href]").unwrap();
+    static ref IMAGE_SELECTOR:Selector = Selector::parse("img[alt]").unwrap();
+
+}
+
+/// Query parameters holding the destination of redirect and tracking links, ex : `https://www.google.com/url?q=...`.
+const REDIRECT_PARAMETERS:[&str; 11] = ["url", "q", "u", "target", "dest", "destination", "redirect", "redirect_url", "redirect_uri", "link", "goto"];
+
+/// The known redirect wrappers : a host, matching its subdomains too, and the path of its redirect endpoint, `None` for hosts which only serve redirects.
+const REDIRECT_ENDPOINTS:[(&str, Option<&str>); 6] = [
+    ("google.com", Some("/url")),
+    ("facebook.com", Some("/l.php")),
+    ("youtube.com", Some("/redirect")),
+    ("linkedin.com", Some("/redir/redirect")),
+    ("safelinks.protection.outlook.com", None),
+    ("slack-redir.net", None),
+];
+
+/// Number of nested redirect links unwrapped at most.
+const MAX_UNWRAPPED_REDIRECTS:u32 = 5;
+
+/// A link found in the content of a message.
+///
+/// The url is the final destination of the link : html entities are decoded and redirect and tracking wrappers are removed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MailLink {
+
+    url:String,
+    raw_url:String,
+    text:String,
+
+}
+
+impl MailLink {
+
+    /// Gets the destination of the link, without its redirect wrappers.
+    pub fn get_url(&self) -> &str {
+        &self.url
+    }
+
+    /// Gets the `href` of the link as written in the message, its entities decoded.
+    pub fn get_raw_url(&self) -> &str {
+        &self.raw_url
+    }
+
+    /// Gets the visible text of the link, or the alternative text of its image.
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+
+    /// Gets the lowercase host of the destination, `None` for links without a host such as `mailto:` ones.
+    pub fn get_domain(&self) -> Option<String> {
+        Url::parse(&self.url).ok()?.host_str().map(|host| host.to_lowercase())
+    }
+
+    /// Returns true if the destination is on `domain` or one of its subdomains.
+    pub fn is_on_domain(&self, domain:&str) -> bool {
+
+        let domain = domain.trim().trim_start_matches('.').to_lowercase();
+
+        match self.get_domain() {
+            Some(host) => host == domain || host.ends_with(&format!(".{}", domain)),
+            None => false,
+        }
+
+    }
+
+    /// Returns true if the visible text contains `text`, ignoring the case.
+    pub fn has_text(&self, text:&str) -> bool {
+        self.text.to_lowercase().contains(&text.trim().to_lowercase())
+    }
+
+}
+
+/// Extracts the links of an html content, in the order they appear.
+pub(crate) fn extract_links(html:&str) -> Vec<MailLink> {
+
+    let fragment = Html::parse_fragment(html);
+
+    fragment.select(&LINK_SELECTOR)
+            .filter_map(|link| {
+
+                let raw_url = decode_entities(link.value().attr("href")?.trim());
+
+                if raw_url.is_empty() || raw_url.starts_with('#') {
+                    return None;
+                }
+
+                let mut text = link.text().collect::<Vec<&str>>().join(" ").split_whitespace().collect::<Vec<&str>>().join(" ");
+
+                if text.is_empty() {
+                    text = link.select(&IMAGE_SELECTOR).filter_map(|image| image.value().attr("alt")).collect::<Vec<&str>>().join(" ").trim().to_string();
+                }
+
+                Some(MailLink {url:unwrap_redirects(&raw_url), raw_url, text})
+
+            })
+            .collect()
+
+}
+
+/// Decodes the entities left in an `href`, which some senders encode twice (`&amp;amp;`).
+fn decode_entities(href:&str) -> String {
+
+    let mut decoded = href.to_string();
+
+    while decoded.contains('&') && decoded.contains(';') {
+
+        match decode_html(&decoded) {
+
+            Ok(next) if next != decoded => decoded = next,
+            _ => break,
+
+        }
+
+    }
+
+    decoded
+
+}
+
+/// Follows the destination of redirect and tracking links until the link is not a known wrapper.
+fn unwrap_redirects(href:&str) -> String {
+
+    let mut current = href.to_string();
+
+    for _ in 0..MAX_UNWRAPPED_REDIRECTS {
+
+        match get_redirect_target(&current) {
+
+            Some(target) => current = target,
+            None => break,
+
+        }
+
+    }
+
+    current
+
+}
+
+fn get_redirect_target(href:&str) -> Option<String> {
+
+    let url = Url::parse(href).ok()?;
+
+    //Proofpoint rewrites the destination in the path : https://urldefense.com/v3/__https://example.com__;!!token
+    if url.host_str() == Some("urldefense.com") {
+
+        let wrapped = href.split_once("/v3/__")?.1;
+        let target = wrapped.rsplit_once("__;").map(|(target, _)| target).unwrap_or(wrapped);
+
+        return is_absolute_http(target).then(|| target.to_string());
+
+    }
+
+    //Only known wrappers are unwrapped : a confirmation link may hold the page to show once confirmed in the same parameters
+    let host = url.host_str()?.to_lowercase();
+    let path = url.path().trim_end_matches('/').to_lowercase();
+
+    let is_wrapper = REDIRECT_ENDPOINTS.iter().any(|(wrapper_host, wrapper_path)| {
+        (host == *wrapper_host || host.ends_with(&format!(".{}", wrapper_host))) && wrapper_path.is_none_or(|wrapper_path| path == wrapper_path)
+    });
+
+    if !is_wrapper {
+        return None;
+    }
+
+    url.query_pairs()
+       .find(|(key, value)| REDIRECT_PARAMETERS.contains(&key.to_lowercase().as_str()) && is_absolute_http(value))
+       .map(|(_, value)| value.trim().to_string())
+
+}
+
+fn is_absolute_http(value:&str) -> bool {
+
+    match Url::parse(value.trim()) {
+
+        Ok(url) => (url.scheme() == "http" || url.scheme() == "https") && url.host_str().is_some(),
+        Err(_) => false,
+
+    }
+
+}
@@ -1,20 +1,37 @@
 use crate::endpoint::*;
-use crate::regexes::MAIL_ID_REGEX;
-use crate::http::{UrlQuery, get_response_content};
+use crate::regexes::{MAIL_ID_REGEX, HREF_MAIL_ID_REGEX, RELATIVE_TIME_REGEX, NAMED_ADDRESS_REGEX, BARE_ADDRESS_REGEX, RECIPIENT_REGEX};
+use crate::http::UrlQuery;
 use crate::errors::GmailnatorError; 
+use crate::client::GmailnatorClient;
+use crate::session::CSRF_TOKEN_FIELD;
+use crate::bulk::BulkInboxIterator;
+use crate::address::{AddressKind, EmailAddress, AliasIterator};
+use crate::watch::{InboxWatcher, WatchSnapshot};
+use crate::render::html_to_text;
+use crate::links::{MailLink, extract_links};
+use crate::codes::{CodeExtractor, extract_default_codes};
+use crate::export::{to_eml, to_mbox_entry, sync_maildir, get_message_key};
 
 use serde::{Serialize, Deserialize};
 use scraper::{Html, Selector};
 use htmlescape::decode_html; 
 use serde_json::from_str;
+use chrono::{DateTime, Utc};
 
-//use futures::;
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
 
 lazy_static! {
 
     static ref SUBJECT_SELECTOR:Selector = Selector::parse("b").unwrap();
     static ref BODY_SELECTOR:Selector = Selector::parse("div").unwrap();
 
+    static ref SUMMARY_LINK_SELECTOR:Selector = Selector::parse("a").unwrap();
+    static ref SUMMARY_CELL_SELECTOR:Selector = Selector::parse("td").unwrap();
+
     static ref BULK_EMAIL_SELECTOR:Selector = Selector::parse("#email-list-message > a").unwrap();
 
 }
@@ -23,10 +40,21 @@ lazy_static! {
 pub type Error = GmailnatorError;
 
 /// A structure that contains an e-mail subject and its raw content which the `decode_content()` method can decode.
-#[derive(Debug, Serialize, Deserialize)]
+/// When the server provides them, it also holds the message id, its sender and the time it was received at.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MailMessage {
     subject:String,
     raw_content:String,
+    #[serde(default)]
+    id:Option<String>,
+    #[serde(default)]
+    sender_name:Option<String>,
+    #[serde(default)]
+    sender_address:Option<String>,
+    #[serde(default)]
+    received:Option<DateTime<Utc>>,
+    #[serde(default)]
+    recipient:Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -35,10 +63,126 @@ struct JsonMailMessage {
     content:String,
 }
 
+#[derive(Deserialize)]
+struct JsonMailListItem {
+    content:String,
+}
+
+/// The information about a message listed in the mailbox, before its content is retrieved.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct MailSummary {
+    pub id:String,
+    pub sender:Option<String>,
+    pub received:Option<DateTime<Utc>>,
+}
+
+impl MailSummary {
+
+    fn from_id(id:&str) -> Self {
+        Self {id:id.to_string(), ..Default::default()}
+    }
+
+    /// Parses a mailbox list item, the id is found in the link and the sender and time in the first and last cells.
+    fn parse(item_html:&str, now:DateTime<Utc>) -> Option<Self> {
+
+        let fragment = Html::parse_fragment(item_html);
+
+        let id = fragment.select(&SUMMARY_LINK_SELECTOR)
+                         .filter_map(|link| link.value().attr("href"))
+                         .find_map(|href| HREF_MAIL_ID_REGEX.captures(href))
+                         .map(|capture| capture[1].to_string())?;
+
+        let cells:Vec<String> = fragment.select(&SUMMARY_CELL_SELECTOR)
+                                        .map(|cell| cell.text().collect::<String>().trim().to_string())
+                                        .collect();
+
+        let sender = cells.first().filter(|_| cells.len() > 1).cloned();
+        let received = cells.last().and_then(|cell| parse_received_time(cell, now));
+
+        Some(Self {id, sender, received})
+
+    }
+
+}
+
+/// Converts the relative times displayed by gmailnator (`5 hrs ago`, `just now`...) to a timestamp.
+pub(crate) fn parse_received_time(text:&str, now:DateTime<Utc>) -> Option<DateTime<Utc>> {
+
+    let text = text.trim();
+
+    if text.eq_ignore_ascii_case("just now") || text.eq_ignore_ascii_case("now") {
+        return Some(now);
+    }
+
+    let capture = RELATIVE_TIME_REGEX.captures(text)?;
+
+    let amount:i64 = match capture[1].to_lowercase().as_str() {
+        "a" | "an" => 1,
+        number => number.parse().ok()?,
+    };
+
+    let seconds_per_unit:i64 = match capture[2].to_lowercase().as_str() {
+        "s" | "sec" | "second" => 1,
+        "m" | "min" | "minute" => 60,
+        "h" | "hr" | "hour" => 60 * 60,
+        "d" | "day" => 24 * 60 * 60,
+        "w" | "week" => 7 * 24 * 60 * 60,
+        "mo" | "month" => 30 * 24 * 60 * 60,
+        _ => 365 * 24 * 60 * 60,
+    };
+
+    let elapsed = chrono::Duration::try_seconds(amount.checked_mul(seconds_per_unit)?)?;
+
+    now.checked_sub_signed(elapsed)
+
+}
+
+/// Splits a sender such as `Name <address@domain.com>` into its name and address.
+fn parse_sender(sender:&str) -> (Option<String>, Option<String>) {
+
+    let sender = sender.trim();
+
+    if let Some(capture) = NAMED_ADDRESS_REGEX.captures(sender) {
+
+        let name = capture[1].trim();
+        let name = if name.is_empty() { None } else { Some(name.to_string()) };
+
+        (name, Some(capture[2].to_string()))
+
+    } else if BARE_ADDRESS_REGEX.is_match(sender) {
+        (None, Some(sender.to_string()))
+    } else if sender.is_empty() {
+        (None, None)
+    } else {
+        (Some(sender.to_string()), None)
+    }
+
+}
+
 impl MailMessage {
 
     pub(crate) fn new(subject:String, raw_content:String) -> Self {
-        Self {subject, raw_content}
+        Self {subject, raw_content, id:None, sender_name:None, sender_address:None, received:None, recipient:None}
+    }
+
+    /// Completes the message with what the mailbox list tells about it.
+    pub(crate) fn with_summary(mut self, summary:&MailSummary) -> Self {
+
+        self.id = Some(summary.id.clone());
+
+        if let Some(sender) = &summary.sender {
+
+            let (name, address) = parse_sender(sender);
+
+            self.sender_name = self.sender_name.or(name);
+            self.sender_address = self.sender_address.or(address);
+
+        }
+
+        self.received = self.received.or(summary.received);
+
+        self
+
     }
 
     pub(crate) fn parse(response_fragment:&str) -> Result<Self, Error> {
@@ -74,8 +218,28 @@ impl MailMessage {
             false => body_item.unwrap().inner_html(),
 
         };
+
+        let mut message = MailMessage::new(subject, raw_body);
+
+        // The divs following the subject hold the sender, the recipient and the received time
+        let now = Utc::now();
+
+        for detail in subject_fragment.select(&BODY_SELECTOR) {
+
+            let text = detail.text().collect::<String>();
+
+            if let Some(capture) = RECIPIENT_REGEX.captures(&text) {
+                message.recipient = message.recipient.or(parse_sender(&capture[1]).1);
+            } else if let Some(received) = parse_received_time(&text, now) {
+                message.received = message.received.or(Some(received));
+            } else if let (name, Some(address)) = parse_sender(&text) {
+                message.sender_name = message.sender_name.or(name);
+                message.sender_address = message.sender_address.or(Some(address));
+            }
+
+        }
         
-        Ok(MailMessage::new(subject, raw_body))
+        Ok(message)
         
     }
 
@@ -95,146 +259,504 @@ impl MailMessage {
     
     }
 
+    /// Renders the content of the message as readable plain text.
+    /// Paragraphs, headings and other blocks are put on their own lines, list items start with `- ` or their number, table cells are separated by ` | `, and scripts and styles are removed.
+    /// ```no_run
+    /// # use gmailnator::GmailnatorInbox;
+    /// # let inbox = GmailnatorInbox::new().unwrap();
+    /// for message in inbox.get_messages_iter().unwrap() {
+    ///     println!("{}\n\n{}", message.get_subject(), message.text());
+    /// }
+    /// ```
+    pub fn text(&self) -> String {
+        html_to_text(&self.raw_content)
+    }
+
+    /// Returns the links of the content, in the order they appear, see [`MailLink`](struct.MailLink.html).
+    /// Their urls are decoded and redirect and tracking wrappers, like `https://www.google.com/url?q=...`, are removed.
+    /// ```no_run
+    /// # use gmailnator::GmailnatorInbox;
+    /// # let inbox = GmailnatorInbox::new().unwrap();
+    /// # let message = inbox.get_messages_iter().unwrap().next().unwrap();
+    /// for link in message.links() {
+    ///     println!("{} -> {}", link.get_text(), link.get_url());
+    /// }
+    /// ```
+    pub fn links(&self) -> Vec<MailLink> {
+        extract_links(&self.raw_content)
+    }
+
+    /// Returns the first link whose visible text contains `text`, ignoring the case, ex : `"confirm"`.
+    pub fn find_link_by_text(&self, text:&str) -> Option<MailLink> {
+        self.links().into_iter().find(|link| link.has_text(text))
+    }
+
+    /// Returns the first link leading to `domain` or one of its subdomains.
+    pub fn find_link_by_domain(&self, domain:&str) -> Option<MailLink> {
+        self.links().into_iter().find(|link| link.is_on_domain(domain))
+    }
+
+    /// Returns the 4 to 8 digit codes found in the subject and the text of the message, the closest to words like "code" or "verification" first.
+    /// Numbers that are part of dates, phone numbers or amounts are ignored, use a [`CodeExtractor`](struct.CodeExtractor.html) for other formats.
+    pub fn extract_codes(&self) -> Vec<String> {
+        extract_default_codes(self)
+    }
+
+    /// Returns the most likely verification code of the message, see [`extract_codes`](#method.extract_codes).
+    pub fn extract_code(&self) -> Option<String> {
+        self.extract_codes().into_iter().next()
+    }
+
+    /// Writes the message as an RFC 5322 `.eml` file that mail clients can open.
+    /// The `From`, `To`, `Subject`, `Date` and `Message-ID` headers are synthesized from what the server told about the message, and the html content is base64 encoded.
+    /// ```no_run
+    /// # use gmailnator::GmailnatorInbox;
+    /// # let inbox = GmailnatorInbox::new().unwrap();
+    /// for message in inbox.get_messages_iter().unwrap() {
+    ///     std::fs::write(format!("{}.eml", message.get_file_key()), message.to_eml()).unwrap();
+    /// }
+    /// ```
+    pub fn to_eml(&self) -> String {
+        to_eml(self, None)
+    }
+
+    /// Gets a key identifying the message that can be used as a file name : its server id without the characters unsafe in file names,
+    /// or a hash of its content if it has no id. Messages are stored under this key by [`sync_to_maildir`](struct.GmailnatorInbox.html#method.sync_to_maildir).
+    pub fn get_file_key(&self) -> String {
+        get_message_key(self)
+    }
+
     /// Gets the message's raw html content with potential html entities still encoded. 
     pub fn get_raw_content(&self) -> &str {
         &self.raw_content
     }
 
+    /// Gets the server's id of the message.
+    pub fn get_id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    /// Gets the display name of the sender, ex : `Google`.
+    pub fn get_sender_name(&self) -> Option<&str> {
+        self.sender_name.as_deref()
+    }
+
+    /// Gets the e-mail address of the sender.
+    pub fn get_sender_address(&self) -> Option<&str> {
+        self.sender_address.as_deref()
+    }
+
+    /// Gets the address the message was sent to, which may be an alias of the inbox address.
+    pub fn get_recipient(&self) -> Option<&str> {
+        self.recipient.as_deref()
+    }
+
+    /// Gets the time the message was received at.
+    /// Gmailnator only displays relative times (`5 hrs ago`), so the precision decreases as the message gets older.
+    pub fn get_received_time(&self) -> Option<DateTime<Utc>> {
+        self.received
+    }
+
 }
 
 /// The library's main object, when instantiated represents a gmailnator inbox associated to an e-mail address.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct GmailnatorInbox {
 
-    mail_address:String,    //COMPLETE E-MAIL | Ex : extmp+blabla@gmail.com
-    temp_server:String,     //SERVER ID       | Ex : extmp
+    pub(crate) mail_address:EmailAddress,    //COMPLETE E-MAIL | Ex : extmp+blabla@gmail.com
+    pub(crate) temp_server:String,     //SERVER ID       | Ex : extmp
+
+    #[serde(default)]
+    pub(crate) kind:AddressKind,
+
+    #[serde(skip)]
+    pub(crate) client:GmailnatorClient,
 
 }
 
-impl GmailnatorInbox {
+impl fmt::Debug for GmailnatorInbox {
 
-    const MIN_BULK_COUNT:u32 = 1;
-    const MAX_BULK_COUNT:u32 = 1000;
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 
-    /// Creates a new inbox. 
-    pub fn new() -> Result<Self, Error> {
-        
-        let email_request = get_request_from_endpoint(GmailnatorEndpoint::GetEmail);
-        let mut mail_query = GmailnatorInbox::get_tokened_query();
-        
-        mail_query.add("action", "GenerateEmail");
-        mail_query.add("data%5B%5D", "2");
- 
-        let response_str = get_response_content(email_request, mail_query)?;
+        f.debug_struct("GmailnatorInbox")
+         .field("mail_address", &self.mail_address.as_str())
+         .field("temp_server", &self.temp_server)
+         .field("kind", &self.kind)
+         .finish()
 
-        let server_id = GmailnatorInbox::get_temp_server_id(&response_str)?;
+    }
 
-        return Ok(
-            Self {
-                mail_address:response_str,
-                temp_server:server_id,
-            }
-        );
+}
 
+impl GmailnatorInbox {
+
+    pub(crate) const MIN_BULK_COUNT:u32 = 1;
+    pub(crate) const MAX_BULK_COUNT:u32 = 1000;
+
+    /// Creates a new inbox with the default [`GmailnatorClient`](struct.GmailnatorClient.html). 
+    pub fn new() -> Result<Self, Error> {
+        GmailnatorClient::default().new_inbox()
     }
  
-    /// Creates the desired amount of inbox.
+    /// Creates the desired amount of inbox with the default [`GmailnatorClient`](struct.GmailnatorClient.html).
     /// The `count` argument must be between 1 and 1000 included. 
     pub fn new_bulk(count:u32) -> Result<Vec<Self>, Error> {
+        GmailnatorClient::default().new_bulk(count)
+    }
 
-        if count < GmailnatorInbox::MIN_BULK_COUNT 
-        || count > GmailnatorInbox::MAX_BULK_COUNT {
-            return Err(Error::InvalidCountError(count));
-        }
-
-        let bulk_request = get_request_from_endpoint(GmailnatorEndpoint::GetEmailBulk);
-
-        let mut bulk_query = GmailnatorInbox::get_tokened_query();
-
-        bulk_query.add("email_list", &(count - 1).to_string());
-        bulk_query.add("email%5B%5D", "2");
+    /// Creates a new inbox whose address is of the given [`AddressKind`](enum.AddressKind.html), with the default [`GmailnatorClient`](struct.GmailnatorClient.html).
+    pub fn new_with_kind(kind:AddressKind) -> Result<Self, Error> {
+        GmailnatorClient::default().new_inbox_with_kind(kind)
+    }
 
-        let response_str = get_response_content(bulk_request, bulk_query)?;
+    /// Creates the desired amount of inbox whose addresses are of the given [`AddressKind`](enum.AddressKind.html), with the default [`GmailnatorClient`](struct.GmailnatorClient.html).
+    /// The `count` argument must be between 1 and 1000 included.
+    pub fn new_bulk_with_kind(count:u32, kind:AddressKind) -> Result<Vec<Self>, Error> {
+        GmailnatorClient::default().new_bulk_with_kind(count, kind)
+    }
 
-        GmailnatorInbox::get_bulk_from_html(&response_str)
-        
+    /// Creates any amount of inbox with the default [`GmailnatorClient`](struct.GmailnatorClient.html), in chunks of at most 1000 addresses.
+    /// The count must be at least 1.
+    pub fn new_bulk_iter(count:u32) -> Result<BulkInboxIterator, Error> {
+        GmailnatorClient::default().new_bulk_iter(count)
     }
 
     /// Creates a new inbox from an  already existing gmailnator address. 
-    /// Warning : an invalid gmailnator address will not return an Error.
-    /// ```
+    /// A malformed address returns an `Error::InvalidAddressError`, but whether the address belongs to gmailnator can't be checked.
+    /// ```no_run
     /// # use gmailnator::GmailnatorInbox;
-    /// let valid   = GmailnatorInbox::from_address("deedtmp+[...]@gmail.com").unwrap();
-    /// let invalid = GmailnatorInbox::from_address("invalid.email@gmail.com").unwrap();
+    /// let valid     = GmailnatorInbox::from_address("deedtmp+abcd@gmail.com").unwrap();
+    /// let unchecked = GmailnatorInbox::from_address("invalid.email@gmail.com").unwrap();
+    /// let malformed = GmailnatorInbox::from_address("<html>Error</html>").unwrap_err();
     /// ```
     pub fn from_address(address:&str) -> Result<Self, Error> {
+        GmailnatorClient::default().inbox_from_address(address)
+    }
 
-        let temp_server_id = GmailnatorInbox::get_temp_server_id(address)?;
+    pub(crate) fn with_client(address:&str, client:GmailnatorClient) -> Result<Self, Error> {
+
+        let address = EmailAddress::parse(address)?;
 
         Ok(Self {
-            mail_address:address.to_string(),
-            temp_server:temp_server_id,
+            temp_server:address.get_message_server_id().to_string(),
+            kind:AddressKind::of_address(address.as_str()),
+            mail_address:address,
+            client,
         })
 
     }
 
+    /// Creates an inbox from the reply of the `GenerateEmail` action, which must be nothing but the address.
+    pub(crate) fn from_generated(reply:&str, client:GmailnatorClient, kind:AddressKind) -> Result<Self, Error> {
+
+        match GmailnatorInbox::with_client(reply, client) {
+
+            Ok(inbox) => Ok(inbox.with_kind(kind)),
+            Err(_) => Err(Error::MailServerParsingError(reply.to_string())),
+
+        }
+
+    }
+
+    /// Sets the kind the address was generated as, instead of the guessed one.
+    pub(crate) fn with_kind(mut self, kind:AddressKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub(crate) fn generate(client:&GmailnatorClient, kind:AddressKind) -> Result<Self, Error> {
+
+        let mail_query = GmailnatorInbox::get_generate_email_query(kind);
+
+        let response_str = client.send(GmailnatorEndpoint::GetEmail, mail_query)?;
+
+        GmailnatorInbox::from_generated(&response_str, client.clone(), kind)
+
+    }
+
+    pub(crate) fn generate_bulk(client:&GmailnatorClient, count:u32, kind:AddressKind) -> Result<Vec<Self>, Error> {
+
+        let bulk_query = GmailnatorInbox::get_bulk_query(count, kind)?;
+
+        let response_str = client.send(GmailnatorEndpoint::GetEmailBulk, bulk_query)?;
+
+        let inbox_list = GmailnatorInbox::get_bulk_addresses_from_html(&response_str)
+                                         .iter()
+                                         .filter_map(|address| GmailnatorInbox::with_client(address, client.clone()).ok())
+                                         .map(|inbox| inbox.with_kind(kind))
+                                         .collect();
+
+        Ok(inbox_list)
+
+    }
+
     /// Returns the received e-mail(s) as an iterator.
     /// It's only when calling `next()` on the iterator that the e-mail data will be queried. 
     pub fn get_messages_iter(&self) -> Result<MailMessageIterator, Error> {
 
-        let message_ids = self.get_inbox_messages_id_collection()?;
-
         let iter = MailMessageIterator {
-            message_ids,
+            inner:self.get_messages_try_iter()?,
+        };
+
+        Ok(iter)
+
+    }
+
+    /// Returns the received e-mail(s) as an iterator of `Result`, a message that fails to be retrieved yields its error.
+    /// This allows to tell an empty inbox from a server failure :
+    /// ```no_run
+    /// # use gmailnator::GmailnatorInbox;
+    /// # let inbox = GmailnatorInbox::new().unwrap();
+    /// for message in inbox.get_messages_try_iter().unwrap() {
+    /// 
+    ///     match message {
+    ///         Ok(message) => println!("{}", message.get_subject()),
+    ///         Err(error) => eprintln!("Could not retrieve a message : {}", error),
+    ///     }
+    /// 
+    /// }
+    /// ```
+    pub fn get_messages_try_iter(&self) -> Result<MailMessageTryIterator, Error> {
+
+        let message_summaries = self.get_inbox_message_summaries()?;
+
+        let iter = MailMessageTryIterator {
+            message_summaries,
             temp_server_identifier:self.temp_server.clone(),
+            client:self.client.clone(),
         };
 
         Ok(iter)
 
     }
 
-    /// Returns the current inbox e-mail address.
-    pub fn get_address(&self) -> &str {
-        &self.mail_address
+    /// Returns a watcher returning only the messages received since its previous poll, see [`InboxWatcher`](struct.InboxWatcher.html).
+    pub fn watch(&self) -> InboxWatcher {
+        InboxWatcher::new(self, Default::default())
     }
 
-    fn get_bulk_from_html(html:&str) -> Result<Vec<Self>, Error> {
+    /// Returns a watcher that won't return the messages already seen by the watcher `snapshot` was taken from.
+    /// The ids of a snapshot taken on another mailbox are ignored.
+    pub fn resume_watch(&self, snapshot:&WatchSnapshot) -> InboxWatcher {
 
-        let document = Html::parse_document(html);
-        let mut emails = document.select(&BULK_EMAIL_SELECTOR);
+        let seen_ids = match snapshot.get_address().is_same_mailbox(&self.mail_address) {
+            true => snapshot.get_seen_ids().iter().cloned().collect(),
+            false => Default::default(),
+        };
+
+        InboxWatcher::new(self, seen_ids)
+
+    }
+
+    /// Retrieves the message whose server id is `message_id`, ex : the id of a message listed by [`get_messages_iter`](#method.get_messages_iter).
+    pub fn get_message(&self, message_id:&str) -> Result<MailMessage, Error> {
+        GmailnatorInbox::get_message_by_id(&self.client, &self.temp_server, &MailSummary::from_id(message_id))
+    }
+
+    /// Blocks until a message matching `predicate` is received and returns it, the inbox is polled every `poll_interval`.
+    /// Only the messages that were not already seen are downloaded on each poll, and a message that can't be retrieved doesn't stop the wait.
+    /// Transport failures, server errors and blocking pages don't stop the wait either, the next poll waiting at least for the `Retry-After` delay if the server asked for one.
+    /// Returns an `Error::WaitTimeoutError` if no matching message arrived before `timeout`.
+    /// ```no_run
+    /// # use gmailnator::GmailnatorInbox;
+    /// use std::time::Duration;
+    /// # let inbox = GmailnatorInbox::new().unwrap();
+    /// let confirmation = inbox.wait_for_message(|m| m.get_subject().contains("Confirm"), Duration::from_secs(120), Duration::from_secs(5));
+    /// ```
+    pub fn wait_for_message<F>(&self, mut predicate:F, timeout:Duration, poll_interval:Duration) -> Result<MailMessage, Error>
+    where F: FnMut(&MailMessage) -> bool {
 
-        let mut inbox_list = Vec::<Self>::new();
+        let deadline = Instant::now() + timeout;
 
-        while let Some(mail_item) = emails.next() {
+        let mut watcher = self.watch();
 
-            let address = mail_item.text().next().unwrap();
-            let inbox_res = Self::from_address(&address);
+        loop {
 
-            if let Ok(inbox) = inbox_res {
+            let mut delay = poll_interval;
 
-                inbox_list.push(inbox);
+            let errors = match watcher.try_poll() {
 
+                Ok(results) => {
+
+                    let mut errors = Vec::new();
+
+                    for result in results {
+
+                        match result {
+                            Ok(message) if predicate(&message) => { return Ok(message); }
+                            Ok(_) => {},
+                            Err(error) => errors.push(error),
+                        }
+
+                    }
+
+                    errors
+
+                },
+                Err(error) if error.is_transport_error() || error.is_blocking_error() || matches!(error, Error::ServerError(_)) => vec![error],
+                Err(error) => { return Err(error); }
+
+            };
+
+            if let Some(retry_after) = errors.iter().filter_map(Error::get_retry_after).max() {
+                delay = delay.max(retry_after);
             }
 
+            let now = Instant::now();
+
+            if now >= deadline {
+                return Err(Error::WaitTimeoutError(timeout));
+            }
+
+            thread::sleep(delay.min(deadline - now));
+
         }
 
-        Ok(inbox_list)
+    }
 
+    /// Blocks until a message matching `predicate` and holding a verification code is received, and returns its most likely code.
+    /// See [`wait_for_message`](#method.wait_for_message) and [`MailMessage::extract_codes`](struct.MailMessage.html#method.extract_codes).
+    /// ```no_run
+    /// # use gmailnator::GmailnatorInbox;
+    /// use std::time::Duration;
+    /// # let inbox = GmailnatorInbox::new().unwrap();
+    /// let code = inbox.wait_for_code(|m| m.get_sender_address() == Some("no-reply@example.com"), Duration::from_secs(120), Duration::from_secs(5)).unwrap();
+    /// ```
+    pub fn wait_for_code<F>(&self, predicate:F, timeout:Duration, poll_interval:Duration) -> Result<String, Error>
+    where F: FnMut(&MailMessage) -> bool {
+        self.wait_for_code_with(&CodeExtractor::new(), predicate, timeout, poll_interval)
     }
 
-    fn get_message_by_id(server_identifier:&str, message_id:&str) -> Result<MailMessage, Error> {
+    /// Like [`wait_for_code`](#method.wait_for_code), with the codes found by `extractor`.
+    pub fn wait_for_code_with<F>(&self, extractor:&CodeExtractor, mut predicate:F, timeout:Duration, poll_interval:Duration) -> Result<String, Error>
+    where F: FnMut(&MailMessage) -> bool {
 
-        let get_message_request = get_request_from_endpoint(GmailnatorEndpoint::GetMessage);
+        let mut code = None;
 
-        let mut get_message_query = GmailnatorInbox::get_tokened_query();
+        self.wait_for_message(|message| {
 
-        get_message_query.add("action", "get_message");
-        get_message_query.add("message_id", message_id);
-        get_message_query.add("email", server_identifier);
+            if predicate(message) {
+                code = extractor.extract_from_message(message).into_iter().next();
+            }
+
+            code.is_some()
+
+        }, timeout, poll_interval)?;
+
+        code.ok_or(Error::WaitTimeoutError(timeout))
+
+    }
+
+    /// Writes every message of the inbox to `writer` in the mbox format (mboxrd), and returns the number of messages written.
+    /// Messages whose recipient is unknown are addressed to the inbox. Returns an `Error::WriteError` if writing fails.
+    /// ```no_run
+    /// # use gmailnator::GmailnatorInbox;
+    /// use std::fs::File;
+    /// # let inbox = GmailnatorInbox::new().unwrap();
+    /// let count = inbox.export_mbox(File::create("inbox.mbox").unwrap()).unwrap();
+    /// ```
+    pub fn export_mbox<W:Write>(&self, mut writer:W) -> Result<usize, Error> {
+
+        let mut count = 0;
+
+        for message in self.get_messages_try_iter()? {
+
+            let entry = to_mbox_entry(&message?, Some(self.get_address()));
+
+            writer.write_all(entry.as_bytes())
+                  .and_then(|_| writer.write_all(b"\n"))
+                  .map_err(Error::WriteError)?;
+
+            count += 1;
+
+        }
+
+        writer.flush().map_err(Error::WriteError)?;
+
+        Ok(count)
+
+    }
+
+    /// Mirrors the inbox in the maildir at `path`, which is created if needed, and returns the number of messages added.
+    /// Messages are keyed by their server id : the ones already in the maildir, even moved to `cur` by a mail client, are not fetched again.
+    /// Returns an `Error::WriteError` if the maildir can't be read or written.
+    /// ```no_run
+    /// # use gmailnator::GmailnatorInbox;
+    /// # let inbox = GmailnatorInbox::new().unwrap();
+    /// let added = inbox.sync_to_maildir("/home/qa/Maildir/.temp-inbox").unwrap();
+    /// ```
+    pub fn sync_to_maildir<P:AsRef<Path>>(&self, path:P) -> Result<usize, Error> {
+        sync_maildir(self, path.as_ref())
+    }
+
+    /// Returns the current inbox e-mail address.
+    pub fn get_address(&self) -> &str {
+        self.mail_address.as_str()
+    }
+
+    /// Returns the inbox e-mail address as an [`EmailAddress`](struct.EmailAddress.html), which holds the server id of the inbox.
+    pub fn get_email_address(&self) -> &EmailAddress {
+        &self.mail_address
+    }
+
+    /// Returns the addresses delivered to this inbox that can be derived from its address without any request : every placement of dots in the account name, on `gmail.com` and `googlemail.com`.
+    /// Inboxes on a public domain have no alias.
+    /// ```no_run
+    /// # use gmailnator::GmailnatorInbox;
+    /// # let inbox = GmailnatorInbox::new().unwrap();
+    /// let signup_addresses:Vec<String> = inbox.aliases().take(50).map(|alias| alias.to_string()).collect();
+    /// ```
+    pub fn aliases(&self) -> AliasIterator {
+        self.mail_address.aliases()
+    }
+
+    /// Returns the address of this inbox with the `+tag` suffix replaced by `tag`, ex : `extmp+newsletter@gmail.com`.
+    /// Returns an `Error::InvalidAddressError` for an inbox on a public domain or a tag with forbidden characters.
+    pub fn alias_with_tag(&self, tag:&str) -> Result<EmailAddress, Error> {
+        self.mail_address.with_tag(tag)
+    }
+
+    /// Returns the address `message` was sent to if it is an alias of this inbox, telling which alias received it.
+    pub fn get_message_alias(&self, message:&MailMessage) -> Option<EmailAddress> {
+
+        let recipient = EmailAddress::parse(message.get_recipient()?).ok()?;
+
+        if recipient.is_same_mailbox(&self.mail_address) {
+            Some(recipient)
+        } else {
+            None
+        }
+
+    }
+
+    /// Returns the kind of the inbox's address, guessed from the address itself if the inbox was not generated by the library.
+    pub fn get_kind(&self) -> AddressKind {
+        self.kind
+    }
+
+    /// Returns the client the inbox sends its requests with.
+    pub fn get_client(&self) -> &GmailnatorClient {
+        &self.client
+    }
+
+    pub(crate) fn get_bulk_addresses_from_html(html:&str) -> Vec<String> {
+
+        let document = Html::parse_document(html);
+
+        document.select(&BULK_EMAIL_SELECTOR)
+                .filter_map(|mail_item| mail_item.text().next())
+                .map(|address| address.trim().to_string())
+                .collect()
+
+    }
+
+    pub(crate) fn get_message_by_id(client:&GmailnatorClient, server_identifier:&str, summary:&MailSummary) -> Result<MailMessage, Error> {
+
+        let get_message_query = GmailnatorInbox::get_message_query(server_identifier, &summary.id);
         
-        let parsable_message = get_response_content(get_message_request, get_message_query)?;
+        let parsable_message = client.send(GmailnatorEndpoint::GetMessage, get_message_query)?;
 
-        MailMessage::parse(&parsable_message)
+        Ok(MailMessage::parse(&parsable_message)?.with_summary(summary))
 
     }
 
@@ -242,56 +764,100 @@ impl GmailnatorInbox {
 
         let mut tokened_query = UrlQuery::new();
         
-        tokened_query.add("csrf_gmailnator_token", "");
+        tokened_query.add(CSRF_TOKEN_FIELD, ""); //The client sets the session token when sending
 
         tokened_query
 
     }
 
-    fn get_temp_server_id(mail_address:&str) -> Result<String, Error> {
+    pub(crate) fn get_generate_email_query(kind:AddressKind) -> UrlQuery {
 
-        let mut server_id = mail_address.split('+');
+        let mut mail_query = GmailnatorInbox::get_tokened_query();
 
-        if let Some(identifier) = server_id.next() {
-            Ok(identifier.to_string())
-        } else {
-            return Err(Error::MailServerParsingError(mail_address.to_string()));
-        }
+        mail_query.add("action", "GenerateEmail");
+        mail_query.add("data%5B%5D", kind.get_form_value());
+
+        mail_query
 
     }
 
-    fn get_inbox_messages_id_collection(&self) -> Result<Vec<String>, Error> {
+    pub(crate) fn get_bulk_query(count:u32, kind:AddressKind) -> Result<UrlQuery, Error> {
+
+        if !(GmailnatorInbox::MIN_BULK_COUNT..=GmailnatorInbox::MAX_BULK_COUNT).contains(&count) {
+            return Err(Error::InvalidCountError(count));
+        }
+
+        let mut bulk_query = GmailnatorInbox::get_tokened_query();
+
+        bulk_query.add("email_list", &(count - 1).to_string());
+        bulk_query.add("email%5B%5D", kind.get_form_value());
 
-        let inbox_request = get_request_from_endpoint(GmailnatorEndpoint::GetInbox);
+        Ok(bulk_query)
+
+    }
+
+    pub(crate) fn get_mailbox_query(mail_address:&str) -> UrlQuery {
 
         let mut query = GmailnatorInbox::get_tokened_query();
         
         query.add("action", "LoadMailList");
-        query.add("Email_address", &self.mail_address);
+        query.add("Email_address", mail_address);
+
+        query
 
-        let response_str = get_response_content(inbox_request, query)?;
+    }
 
-        let mut id_collection = Vec::<String>::new();
+    pub(crate) fn get_message_query(server_identifier:&str, message_id:&str) -> UrlQuery {
 
-        // Gets first capture group where the mail id is stored
-        for id in MAIL_ID_REGEX.captures_iter(&response_str).map( |capture| capture.get(1).unwrap()) {
+        let mut get_message_query = GmailnatorInbox::get_tokened_query();
+
+        get_message_query.add("action", "get_message");
+        get_message_query.add("message_id", message_id);
+        get_message_query.add("email", server_identifier);
+
+        get_message_query
+
+    }
 
-            let id = id.as_str().to_string();
+    pub(crate) fn get_message_summaries_from_response(response_str:&str) -> Vec<MailSummary> {
 
-            id_collection.push(id);
+        let now = Utc::now();
+
+        if let Ok(items) = from_str::<Vec<JsonMailListItem>>(response_str) {
+
+            let summaries:Vec<MailSummary> = items.iter()
+                                                  .filter_map(|item| MailSummary::parse(&item.content, now))
+                                                  .collect();
+
+            if !summaries.is_empty() {
+                return summaries;
+            }
 
         }
 
-        Ok(id_collection)
+        // Gets first capture group where the mail id is stored
+        MAIL_ID_REGEX.captures_iter(response_str)
+                     .map(|capture| MailSummary::from_id(capture.get(1).unwrap().as_str()))
+                     .collect()
+
+    }
+
+    pub(crate) fn get_inbox_message_summaries(&self) -> Result<Vec<MailSummary>, Error> {
+
+        let query = GmailnatorInbox::get_mailbox_query(self.mail_address.as_str());
+
+        let response_str = self.client.send(GmailnatorEndpoint::GetInbox, query)?;
+
+        Ok(GmailnatorInbox::get_message_summaries_from_response(&response_str))
 
     }
 
 }
 
 /// An `Iterator` whose purpose is to reduce resource consumption by only requesting message subject and content to the server when `next()` is called.
+/// The iteration stops at the first message that could not be retrieved, use [`MailMessageTryIterator`](struct.MailMessageTryIterator.html) to get the errors.
 pub struct MailMessageIterator {
-    message_ids:Vec<String>,
-    temp_server_identifier:String,
+    inner:MailMessageTryIterator,
 }
 
 impl Iterator for MailMessageIterator {
@@ -300,14 +866,29 @@ impl Iterator for MailMessageIterator {
 
     fn next(&mut self) -> Option<Self::Item> {
 
-        if let Some(id) = self.message_ids.pop() {
+        self.inner.next()?.ok()
 
-            GmailnatorInbox::get_message_by_id(&self.temp_server_identifier, &id).ok()
+    }
 
-        } else {
-            None
-        }
+}
+
+/// Like [`MailMessageIterator`](struct.MailMessageIterator.html), but yields the error of every message that could not be retrieved or parsed instead of stopping.
+pub struct MailMessageTryIterator {
+    message_summaries:Vec<MailSummary>,
+    temp_server_identifier:String,
+    client:GmailnatorClient,
+}
+
+impl Iterator for MailMessageTryIterator {
+
+    type Item = Result<MailMessage, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+
+        let summary = self.message_summaries.pop()?;
+
+        Some(GmailnatorInbox::get_message_by_id(&self.client, &self.temp_server_identifier, &summary))
 
     }
 
-}
\ No newline at end of file
+}
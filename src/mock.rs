@@ -0,0 +1,494 @@
+use crate::client::{GmailnatorClient, GmailnatorClientBuilder};
+use crate::session::{CSRF_COOKIE_NAME, CSRF_TOKEN_FIELD};
+use crate::address::EmailAddress;
+
+use htmlescape::encode_minimal;
+use serde_json::json;
+use tiny_http::{Header, Method, Request, Response, Server};
+use url::form_urlencoded;
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// The message answered by gmailnator when the csrf token is missing or invalid.
+const CSRF_REJECTION_MESSAGE:&str = "The action you have requested is not allowed.";
+
+/// A message injected in a [`MockServer`](struct.MockServer.html) inbox.
+#[derive(Debug, Clone)]
+pub struct MockMessage {
+
+    subject:String,
+    content:String,
+    sender:String,
+    received:String,
+
+}
+
+impl MockMessage {
+
+    /// Creates a message with an html `content`, sent by `sender@example.com` just now.
+    pub fn new(subject:&str, content:&str) -> Self {
+
+        Self {
+            subject:subject.to_string(),
+            content:content.to_string(),
+            sender:"sender@example.com".to_string(),
+            received:"just now".to_string(),
+        }
+
+    }
+
+    /// Sets the sender as displayed by gmailnator, ex : `Google <no-reply@accounts.google.com>`.
+    pub fn with_sender(mut self, sender:&str) -> Self {
+        self.sender = sender.to_string();
+        self
+    }
+
+    /// Sets the relative receiving time as displayed by gmailnator, ex : `5 hrs ago`.
+    pub fn with_received(mut self, received:&str) -> Self {
+        self.received = received.to_string();
+        self
+    }
+
+}
+
+/// A request received by a [`MockServer`](struct.MockServer.html).
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+
+    /// The http method, ex : `POST`.
+    pub method:String,
+
+    /// The path of the url, ex : `/mailbox/mailboxquery`.
+    pub path:String,
+
+    /// The decoded form fields of the body.
+    pub form:Vec<(String, String)>,
+
+}
+
+impl MockRequest {
+
+    /// Gets the value of the `key` form field.
+    pub fn get_field(&self, key:&str) -> Option<&str> {
+
+        self.form.iter()
+                 .find(|(field, _)| field == key)
+                 .map(|(_, value)| value.as_str())
+
+    }
+
+}
+
+/// A response queued to be served instead of the next endpoint response.
+struct QueuedReply {
+
+    status:u16,
+    headers:Vec<(String, String)>,
+    body:String,
+
+}
+
+struct StoredMessage {
+
+    number:u64,
+    id:String,
+    recipient:String,   //The address the message was injected to, as given
+    message:MockMessage,
+
+}
+
+struct MockState {
+
+    base_url:String,
+    generated_count:u64,
+    message_count:u64,
+    mailboxes:BTreeMap<String, Vec<StoredMessage>>,   //Lowercase address -> messages
+
+    csrf_token:String,
+    csrf_generation:u64,
+    enforce_csrf:bool,
+
+    queued_replies:VecDeque<QueuedReply>,
+    requests:Vec<MockRequest>,
+
+}
+
+impl MockState {
+
+    /// Creates an address of the kind selected by the `data[]` or `email[]` form value.
+    fn generate_address(&mut self, kind:Option<&str>) -> String {
+
+        self.generated_count += 1;
+
+        let address = match kind {
+            Some("1") => format!("mock{}@gmailnator.com", self.generated_count),
+            Some("3") => format!("m.ock.{}@gmail.com", self.generated_count),
+            _ => format!("mock{}+{:x}@gmail.com", self.generated_count, 0xa11a5 * self.generated_count),
+        };
+
+        self.mailboxes.entry(address.to_lowercase()).or_default();
+
+        address
+
+    }
+
+    fn rotate_csrf_token(&mut self) {
+        self.csrf_generation += 1;
+        self.csrf_token = format!("{:032x}", 0x5eed_u64.wrapping_mul(self.csrf_generation).wrapping_add(0xc5f_0000));
+    }
+
+}
+
+/// A local stand-in for gmailnator, serving the endpoints used by the library on `127.0.0.1`.
+///
+/// Its inboxes are scriptable : messages can be injected in any address, failures can be queued
+/// and the csrf token can be rotated to check how a client recovers.
+/// ```
+/// use gmailnator::{MockServer, MockMessage};
+///
+/// let server = MockServer::start().unwrap();
+/// let inbox = server.client().new_inbox().unwrap();
+///
+/// server.inject_message(inbox.get_address(), MockMessage::new("Welcome", "<p>Hello !</p>"));
+///
+/// let messages:Vec<_> = inbox.get_messages_iter().unwrap().collect();
+///
+/// assert_eq!(messages[0].get_subject(), "Welcome");
+/// ```
+pub struct MockServer {
+
+    url:String,
+    server:Arc<Server>,
+    state:Arc<Mutex<MockState>>,
+    handle:Option<JoinHandle<()>>,
+
+}
+
+impl MockServer {
+
+    /// Starts a server listening on a free port of `127.0.0.1`.
+    pub fn start() -> io::Result<Self> {
+
+        let server = Server::http("127.0.0.1:0").map_err(io::Error::other)?;
+        let server = Arc::new(server);
+
+        let address = server.server_addr()
+                            .to_ip()
+                            .ok_or_else(|| io::Error::other("the mock server is not listening on an ip address"))?;
+
+        let url = format!("http://{}", address);
+
+        let mut state = MockState {
+            base_url:url.clone(),
+            generated_count:0,
+            message_count:0,
+            mailboxes:BTreeMap::new(),
+            csrf_token:String::new(),
+            csrf_generation:0,
+            enforce_csrf:true,
+            queued_replies:VecDeque::new(),
+            requests:Vec::new(),
+        };
+
+        state.rotate_csrf_token();
+
+        let state = Arc::new(Mutex::new(state));
+
+        let handle = {
+
+            let server = Arc::clone(&server);
+            let state = Arc::clone(&state);
+
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    handle_request(&state, request);
+                }
+            })
+
+        };
+
+        Ok(Self {url, server, state, handle:Some(handle)})
+
+    }
+
+    /// Returns the base url of the server, ex : `http://127.0.0.1:41234`.
+    pub fn get_url(&self) -> &str {
+        &self.url
+    }
+
+    /// Returns a client builder whose base url is the server's.
+    pub fn client_builder(&self) -> GmailnatorClientBuilder {
+        GmailnatorClient::builder().base_url(&self.url)
+    }
+
+    /// Returns a client sending its requests to the server.
+    pub fn client(&self) -> GmailnatorClient {
+        self.client_builder().build().expect("The mock server url is a valid base url.")
+    }
+
+    /// Creates a new `+` alias address, like the `GenerateEmail` action does.
+    pub fn create_address(&self) -> String {
+        self.state.lock().unwrap().generate_address(None)
+    }
+
+    /// Delivers `message` to `address` and returns the id of the message.
+    pub fn inject_message(&self, address:&str, message:MockMessage) -> String {
+
+        let mut state = self.state.lock().unwrap();
+
+        state.message_count += 1;
+
+        let number = state.message_count;
+        let id = format!("{:x}", 0x17a0_0000_u64 + number);
+
+        state.mailboxes
+             .entry(address.to_lowercase())
+             .or_default()
+             .push(StoredMessage {number, id:id.clone(), recipient:address.to_string(), message});
+
+        id
+
+    }
+
+    /// Answers the next `count` requests to the endpoints with the `status` error.
+    pub fn fail_next(&self, status:u16, count:usize) {
+
+        for _ in 0..count {
+            self.respond_next(status, &[], "");
+        }
+
+    }
+
+    /// Answers the next request to the endpoints with the given response, ex : a rate limit or a challenge page.
+    /// Several calls queue several responses.
+    pub fn respond_next(&self, status:u16, headers:&[(&str, &str)], body:&str) {
+
+        let reply = QueuedReply {
+            status,
+            headers:headers.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect(),
+            body:body.to_string(),
+        };
+
+        self.state.lock().unwrap().queued_replies.push_back(reply);
+
+    }
+
+    /// Changes the csrf token, the requests still using the previous one get rejected.
+    pub fn rotate_csrf_token(&self) {
+        self.state.lock().unwrap().rotate_csrf_token();
+    }
+
+    /// Sets whether requests without a valid csrf token are rejected, which is the default.
+    pub fn set_csrf_enforced(&self, enforced:bool) {
+        self.state.lock().unwrap().enforce_csrf = enforced;
+    }
+
+    /// Returns every request received so far.
+    pub fn get_requests(&self) -> Vec<MockRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+}
+
+impl Drop for MockServer {
+
+    fn drop(&mut self) {
+
+        self.server.unblock();
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+
+    }
+
+}
+
+fn handle_request(state:&Mutex<MockState>, mut request:Request) {
+
+    let mut body = String::new();
+
+    if request.as_reader().read_to_string(&mut body).is_err() {
+        let _ = request.respond(Response::from_string("").with_status_code(400));
+        return;
+    }
+
+    let path = request.url().split('?').next().unwrap_or_default().to_string();
+    let form:Vec<(String, String)> = form_urlencoded::parse(body.as_bytes()).into_owned().collect();
+
+    let cookies = request.headers()
+                         .iter()
+                         .filter(|header| header.field.equiv("Cookie"))
+                         .map(|header| header.value.as_str().to_string())
+                         .collect::<Vec<String>>()
+                         .join("; ");
+
+    let mock_request = MockRequest {method:request.method().to_string(), path, form};
+
+    let reply = {
+
+        let mut state = state.lock().unwrap();
+
+        state.requests.push(mock_request.clone());
+
+        let queued_reply = match *request.method() {
+            Method::Post => state.queued_replies.pop_front(),
+            _ => None,
+        };
+
+        queued_reply.unwrap_or_else(|| {
+
+            let (status, body) = route(&mut state, request.method(), &mock_request, &cookies);
+            let set_cookie = format!("{}={}; path=/", CSRF_COOKIE_NAME, state.csrf_token);
+
+            QueuedReply {status, headers:vec![("Set-Cookie".to_string(), set_cookie)], body}
+
+        })
+
+    };
+
+    let mut response = Response::from_string(reply.body).with_status_code(reply.status);
+
+    for (name, value) in &reply.headers {
+        if let Ok(header) = Header::from_bytes(name.as_bytes(), value.as_bytes()) {
+            response.add_header(header);
+        }
+    }
+
+    let _ = request.respond(response);
+
+}
+
+fn route(state:&mut MockState, method:&Method, request:&MockRequest, cookies:&str) -> (u16, String) {
+
+    if *method == Method::Get && request.path == "/" {
+
+        let landing_page = format!("<html><body><form><input type=\"hidden\" name=\"{}\" value=\"{}\"></form></body></html>", CSRF_TOKEN_FIELD, state.csrf_token);
+
+        return (200, landing_page);
+
+    }
+
+    if *method != Method::Post {
+        return (404, String::new());
+    }
+
+    if state.enforce_csrf {
+
+        let expected_cookie = format!("{}={}", CSRF_COOKIE_NAME, state.csrf_token);
+
+        let has_token = request.get_field(CSRF_TOKEN_FIELD) == Some(state.csrf_token.as_str());
+        let has_cookie = cookies.split(';').any(|cookie| cookie.trim() == expected_cookie);
+
+        if !has_token || !has_cookie {
+            return (403, CSRF_REJECTION_MESSAGE.to_string());
+        }
+
+    }
+
+    match request.path.as_str() {
+
+        "/index/indexquery" => (200, state.generate_address(request.get_field("data[]"))),
+        "/bulk-emails" => bulk_emails(state, request),
+        "/mailbox/mailboxquery" => mailbox_list(state, request),
+        "/mailbox/get_single_message" => single_message(state, request),
+        _ => (404, String::new()),
+
+    }
+
+}
+
+fn bulk_emails(state:&mut MockState, request:&MockRequest) -> (u16, String) {
+
+    let count = match request.get_field("email_list").and_then(|count| count.parse::<u64>().ok()) {
+        Some(count) => count + 1,
+        None => { return (400, String::new()); }
+    };
+
+    let kind = request.get_field("email[]");
+
+    let links:String = (0..count).map(|_| format!("<a href=\"#\">{}</a>", state.generate_address(kind))).collect();
+
+    (200, format!("<html><body><div id=\"email-list-message\">{}</div></body></html>", links))
+
+}
+
+/// Serializes to json the way gmailnator does, with escaped slashes.
+fn to_gmailnator_json(value:&serde_json::Value) -> String {
+    value.to_string().replace('/', "\\/")
+}
+
+/// The mailbox an address is delivered to, every variant of a gmail address shares the same one.
+/// The messages of a tagged alias are fetched with the bare local part, which is read as a gmail account.
+fn get_server_id(address:&str) -> String {
+
+    EmailAddress::parse(address).map(|address| address.get_server_id())
+                                .unwrap_or_else(|_| address.replace('.', "").to_lowercase())
+
+}
+
+/// The messages delivered to the mailbox of `server_id`, in the order they were received.
+fn get_mailbox_messages<'a>(state:&'a MockState, server_id:&str) -> Vec<&'a StoredMessage> {
+
+    let mut messages:Vec<&StoredMessage> = state.mailboxes
+                                                .iter()
+                                                .filter(|(address, _)| get_server_id(address) == server_id)
+                                                .flat_map(|(_, messages)| messages.iter())
+                                                .collect();
+
+    messages.sort_by_key(|stored| stored.number);
+
+    messages
+
+}
+
+fn mailbox_list(state:&mut MockState, request:&MockRequest) -> (u16, String) {
+
+    let server_id = get_server_id(request.get_field("Email_address").unwrap_or_default());
+
+    let items:Vec<serde_json::Value> = get_mailbox_messages(state, &server_id)
+                                            .iter()
+                                            .map(|stored| {
+
+                                                let content = format!(
+                                                    "<a href=\"{}/{}/messageid/#{}\"><table class=\"message_container\"><tbody><tr><td>{}</td><td>{}</td><td class=\"text-right\">{}</td></tr></tbody></table></a>",
+                                                    state.base_url, server_id, stored.id,
+                                                    encode_minimal(&stored.message.sender), encode_minimal(&stored.message.subject), encode_minimal(&stored.message.received),
+                                                );
+
+                                                json!({"content":content})
+
+                                            })
+                                            .collect();
+
+    (200, to_gmailnator_json(&serde_json::Value::Array(items)))
+
+}
+
+fn single_message(state:&mut MockState, request:&MockRequest) -> (u16, String) {
+
+    let id = request.get_field("message_id").unwrap_or_default();
+    let server_id = get_server_id(request.get_field("email").unwrap_or_default());
+
+    let found = get_mailbox_messages(state, &server_id).into_iter().find(|stored| stored.id == id);
+
+    match found {
+
+        Some(stored) => {
+
+            let subject = format!(
+                "<b>{}</b><div>{}</div><div>To: {}</div><div>{}<hr /></div>",
+                encode_minimal(&stored.message.subject), encode_minimal(&stored.message.sender), encode_minimal(&stored.recipient), encode_minimal(&stored.message.received),
+            );
+
+            (200, to_gmailnator_json(&json!({"subject":subject, "content":stored.message.content})))
+
+        },
+        None => (500, String::new()),
+
+    }
+
+}
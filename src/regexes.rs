@@ -3,5 +3,12 @@ use regex::Regex;
 lazy_static! {
 
     pub static ref MAIL_ID_REGEX:Regex = Regex::new(r"messageid\\/#(.*?)\\").unwrap();
+    pub static ref HREF_MAIL_ID_REGEX:Regex = Regex::new(r"messageid/#([^/?#\s]+)").unwrap();
+
+    pub static ref RELATIVE_TIME_REGEX:Regex = Regex::new(r"(?i)^(\d+|an?)\s*(s|sec|second|m|min|minute|h|hr|hour|d|day|w|week|mo|month|y|yr|year)s?\s+ago$").unwrap();
+
+    pub static ref NAMED_ADDRESS_REGEX:Regex = Regex::new(r#"^\s*"?([^"<]*?)"?\s*<([^<>\s]+@[^<>\s]+)>\s*$"#).unwrap();
+    pub static ref RECIPIENT_REGEX:Regex = Regex::new(r"(?i)^\s*(?:delivered-to|to)\s*:\s*(.+?)\s*$").unwrap();
+    pub static ref BARE_ADDRESS_REGEX:Regex = Regex::new(r"^[^@\s<>]+@[^@\s<>]+\.[^@\s<>]+$").unwrap();
 
 }
\ No newline at end of file
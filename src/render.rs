@@ -0,0 +1,233 @@
+use scraper::{Html, ElementRef};
+use scraper::node::Node;
+
+/// Elements whose content is never displayed.
+const SKIPPED_ELEMENTS:[&str; 9] = ["script", "style", "head", "title", "noscript", "template", "meta", "link", "object"];
+
+/// Elements separated from their surroundings by a blank line.
+const PARAGRAPH_ELEMENTS:[&str; 11] = ["p", "h1", "h2", "h3", "h4", "h5", "h6", "blockquote", "table", "dl", "hr"];
+
+/// Elements starting on a new line.
+const BLOCK_ELEMENTS:[&str; 19] = ["div", "section", "article", "header", "footer", "nav", "main", "aside", "form", "center", "address",
+                                   "tr", "thead", "tbody", "tfoot", "dt", "dd", "figure", "caption"];
+
+/// Characters used to pad the preview text of newsletters, they are invisible once rendered.
+const INVISIBLE_CHARACTERS:[char; 5] = ['\u{200b}', '\u{200c}', '\u{200d}', '\u{feff}', '\u{034f}'];
+
+/// Renders an html document or fragment as plain text : blocks are put on their own lines, list items get a marker and table cells are separated by `|`.
+pub(crate) fn html_to_text(html:&str) -> String {
+
+    let fragment = Html::parse_fragment(html);
+
+    let mut writer = TextWriter::default();
+
+    writer.render_children(fragment.root_element());
+
+    writer.finish()
+
+}
+
+#[derive(Default)]
+struct TextWriter {
+
+    text:String,
+
+    newlines:usize,         //Line breaks to write before the next word
+    space:bool,             //Whether a space separates the next word from the previous one
+    separator:bool,         //Whether a table cell separates the next word from the previous one
+    prefix:Option<String>,  //List item marker written before the next word
+
+    lists:Vec<Option<u32>>, //Number of the last item of each open list, None for unordered lists
+    preformatted:u32,
+
+}
+
+impl TextWriter {
+
+    fn is_at_line_start(&self) -> bool {
+        self.text.is_empty() || self.text.ends_with('\n')
+    }
+
+    /// Ends the current line, a count of 2 leaves a blank line. The breaks are only written if some text follows.
+    fn break_line(&mut self, count:usize) {
+        self.newlines = self.newlines.max(count);
+    }
+
+    /// Writes what has to precede the next word : line breaks, indentation and list marker, or a separator.
+    fn start_word(&mut self) {
+
+        if !self.text.is_empty() {
+
+            let written = if self.text.ends_with('\n') { 1 } else { 0 };
+
+            for _ in written..self.newlines {
+                self.text.push('\n');
+            }
+
+        }
+
+        self.newlines = 0;
+
+        if self.is_at_line_start() {
+
+            self.text.push_str(&"  ".repeat(self.lists.len().saturating_sub(1)));
+
+            if let Some(prefix) = self.prefix.take() {
+                self.text.push_str(&prefix);
+            }
+
+        } else if self.separator {
+            self.text.push_str(" | ");
+        } else if self.space {
+            self.text.push(' ');
+        }
+
+        self.space = false;
+        self.separator = false;
+
+    }
+
+    fn write_text(&mut self, text:&str) {
+
+        if self.preformatted > 0 {
+
+            for (index, line) in text.split('\n').enumerate() {
+
+                if index > 0 {
+                    self.newlines += 1;
+                }
+
+                if !line.trim().is_empty() {
+                    self.start_word();
+                    self.text.push_str(line.trim_end());
+                }
+
+            }
+
+            return;
+
+        }
+
+        if text.starts_with(char::is_whitespace) {
+            self.space = true;
+        }
+
+        let words = text.split_whitespace()
+                        .map(|word| word.replace(&INVISIBLE_CHARACTERS[..], ""))
+                        .filter(|word| !word.is_empty());
+
+        for (index, word) in words.enumerate() {
+
+            if index > 0 {
+                self.space = true;
+            }
+
+            self.start_word();
+            self.text.push_str(&word);
+
+        }
+
+        if text.ends_with(char::is_whitespace) {
+            self.space = true;
+        }
+
+    }
+
+    fn render_children(&mut self, element:ElementRef) {
+
+        for child in element.children() {
+
+            match child.value() {
+
+                Node::Text(text) => self.write_text(text),
+                Node::Element(_) => if let Some(child) = ElementRef::wrap(child) { self.render(child) },
+                _ => {},
+
+            }
+
+        }
+
+    }
+
+    fn render(&mut self, element:ElementRef) {
+
+        let name = element.value().name();
+
+        if SKIPPED_ELEMENTS.contains(&name) {
+            return;
+        }
+
+        match name {
+
+            "br" => {
+                self.newlines = (self.newlines + 1).min(2);
+            },
+            "ul" | "ol" => {
+
+                let breaks = if self.lists.is_empty() { 2 } else { 1 };
+
+                let start = element.value().attr("start").and_then(|start| start.trim().parse::<u32>().ok()).unwrap_or(1);
+
+                self.break_line(breaks);
+                self.lists.push(if name == "ol" { Some(start.saturating_sub(1)) } else { None });
+                self.render_children(element);
+                self.lists.pop();
+                self.break_line(breaks);
+
+            },
+            "li" => {
+
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => { *number += 1; format!("{}. ", number) },
+                    _ => "- ".to_string(),
+                };
+
+                self.break_line(1);
+                self.prefix = Some(marker);
+                self.render_children(element);
+                self.prefix = None;
+                self.break_line(1);
+
+            },
+            "td" | "th" => {
+
+                self.separator = true;
+                self.render_children(element);
+                self.space = true;
+
+            },
+            "pre" => {
+
+                self.break_line(2);
+                self.preformatted += 1;
+                self.render_children(element);
+                self.preformatted -= 1;
+                self.break_line(2);
+
+            },
+            _ => {
+
+                let breaks = if PARAGRAPH_ELEMENTS.contains(&name) { 2 } else if BLOCK_ELEMENTS.contains(&name) { 1 } else { 0 };
+
+                self.break_line(breaks);
+                self.render_children(element);
+                self.break_line(breaks);
+
+            },
+
+        }
+
+    }
+
+    fn finish(self) -> String {
+
+        self.text.lines()
+                 .map(str::trim_end)
+                 .collect::<Vec<&str>>()
+                 .join("\n")
+                 .trim_matches('\n')
+                 .to_string()
+
+    }
+
+}
@@ -0,0 +1,179 @@
+use crate::http::QUERY_MAX_TRY;
+
+use chrono::{DateTime, Utc};
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The statuses retried by default : internal errors, unavailable gateways and rate limits.
+const DEFAULT_RETRYABLE_STATUSES:[u16; 5] = [429, 500, 502, 503, 504];
+
+const DEFAULT_INITIAL_BACKOFF:Duration = Duration::from_millis(500);
+const DEFAULT_MAX_BACKOFF:Duration = Duration::from_secs(30);
+const DEFAULT_MAX_RETRY_AFTER:Duration = Duration::from_secs(60);
+
+/// Decides which failed requests are sent again, how many times and after how long.
+///
+/// The delay before each new attempt doubles from the initial backoff up to the maximum one, with a random jitter of up to half of it.
+/// When the server sends a `Retry-After` header, its delay is used instead, unless it is longer than the maximum accepted one.
+/// ```
+/// use std::time::Duration;
+/// use gmailnator::{GmailnatorClient, RetryPolicy};
+///
+/// let policy = RetryPolicy::new()
+///                          .with_max_attempts(5)
+///                          .with_retryable_statuses(&[429, 502, 503])
+///                          .with_backoff(Duration::from_secs(1), Duration::from_secs(20));
+///
+/// let client = GmailnatorClient::builder().retry_policy(policy).build().unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+
+    max_attempts:u32,
+    retryable_statuses:Vec<u16>,
+    retry_transport_errors:bool,
+
+    initial_backoff:Duration,
+    max_backoff:Duration,
+    jitter:bool,
+
+    honor_retry_after:bool,
+    max_retry_after:Duration,
+
+}
+
+impl RetryPolicy {
+
+    /// Creates the default policy : 2 attempts, retrying 429, 500, 502, 503, 504 and transport errors after 500ms.
+    pub fn new() -> Self {
+
+        Self {
+            max_attempts:QUERY_MAX_TRY,
+            retryable_statuses:DEFAULT_RETRYABLE_STATUSES.to_vec(),
+            retry_transport_errors:true,
+            initial_backoff:DEFAULT_INITIAL_BACKOFF,
+            max_backoff:DEFAULT_MAX_BACKOFF,
+            jitter:true,
+            honor_retry_after:true,
+            max_retry_after:DEFAULT_MAX_RETRY_AFTER,
+        }
+
+    }
+
+    /// Creates a policy sending every request only once.
+    pub fn never() -> Self {
+        Self::new().with_max_attempts(1)
+    }
+
+    /// Sets how many times a request is sent at most, including the first time.
+    pub fn with_max_attempts(mut self, max_attempts:u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Sets the http statuses after which a request is sent again.
+    pub fn with_retryable_statuses(mut self, statuses:&[u16]) -> Self {
+        self.retryable_statuses = statuses.to_vec();
+        self
+    }
+
+    /// Sets whether a request is sent again when no response was received at all.
+    pub fn with_transport_errors_retried(mut self, retried:bool) -> Self {
+        self.retry_transport_errors = retried;
+        self
+    }
+
+    /// Sets the delay before the second attempt and the maximum delay between two attempts.
+    pub fn with_backoff(mut self, initial:Duration, max:Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Sets whether the delays are randomly shortened by up to half, to spread the retries of concurrent clients.
+    pub fn with_jitter(mut self, jitter:bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Sets whether the `Retry-After` header is followed, and the longest delay it may ask for before the request is given up.
+    pub fn with_retry_after(mut self, honored:bool, max_delay:Duration) -> Self {
+        self.honor_retry_after = honored;
+        self.max_retry_after = max_delay;
+        self
+    }
+
+    /// Gets how many times a request is sent at most.
+    pub fn get_max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Returns true if a response with the `status` http status may be retried.
+    pub fn is_retryable_status(&self, status:u16) -> bool {
+        self.retryable_statuses.contains(&status)
+    }
+
+    /// Returns true if a request that got no response may be retried.
+    pub fn is_retrying_transport_errors(&self) -> bool {
+        self.retry_transport_errors
+    }
+
+    /// Returns how long to wait after the failed attempt number `attempt` (starting at 1), or `None` if the request must be given up.
+    pub fn get_delay(&self, attempt:u32, retry_after:Option<Duration>) -> Option<Duration> {
+
+        if attempt >= self.max_attempts {
+            return None;
+        }
+
+        if let (true, Some(retry_after)) = (self.honor_retry_after, retry_after) {
+            return if retry_after <= self.max_retry_after { Some(retry_after) } else { None };
+        }
+
+        let exponent = (attempt - 1).min(31);
+        let backoff = self.initial_backoff.saturating_mul(1 << exponent).min(self.max_backoff);
+
+        if self.jitter {
+            Some(backoff / 2 + backoff.mul_f64(random_fraction() / 2.0))
+        } else {
+            Some(backoff)
+        }
+
+    }
+
+}
+
+impl Default for RetryPolicy {
+
+    fn default() -> Self {
+        Self::new()
+    }
+
+}
+
+/// Parses a `Retry-After` header value, either a number of seconds or an http date.
+pub(crate) fn parse_retry_after(value:&str, now:DateTime<Utc>) -> Option<Duration> {
+
+    let value = value.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+
+    Some((date.with_timezone(&Utc) - now).to_std().unwrap_or_default())
+
+}
+
+/// A pseudo-random number in `[0, 1)`, good enough to spread retries.
+fn random_fraction() -> f64 {
+
+    let mut hasher = RandomState::new().build_hasher();
+
+    hasher.write_u128(SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos());
+
+    (hasher.finish() >> 11) as f64 / (1_u64 << 53) as f64
+
+}
@@ -0,0 +1,302 @@
+use crate::endpoint::{GmailnatorRequest, HttpMethod};
+use crate::client::ClientConfig;
+use crate::transport::{HttpTransport, HttpResponse};
+use crate::mail::Error;
+use crate::http::{get_blocking_error, get_status_error};
+#[cfg(feature = "async")]
+use crate::async_api::AsyncHttpTransport;
+
+use scraper::{Html, Selector};
+#[cfg(feature = "async")]
+use futures::channel::oneshot;
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, Condvar};
+
+/// Name of the cookie holding the csrf token.
+pub const CSRF_COOKIE_NAME:&str = "csrf_gmailnator_cookie";
+
+/// Name of the form field the csrf token must be sent in.
+pub const CSRF_TOKEN_FIELD:&str = "csrf_gmailnator_token";
+
+/// The statuses the server answers with when it rejects the csrf token.
+const CSRF_REJECTION_STATUSES:[u16; 2] = [403, 419];
+
+lazy_static! {
+
+    static ref CSRF_INPUT_SELECTOR:Selector = Selector::parse("input[name=csrf_gmailnator_token]").unwrap();
+    static ref CSRF_META_SELECTOR:Selector = Selector::parse("meta[name=csrf-token]").unwrap();
+
+}
+
+#[derive(Default)]
+struct SessionState {
+
+    cookies:BTreeMap<String, String>,
+    token:Option<String>,   //None until the landing page was loaded
+
+    handshaking:bool,       //Whether a caller is loading the landing page, the others wait for its token
+    #[cfg(feature = "async")]
+    async_waiters:Vec<oneshot::Sender<()>>,
+
+}
+
+/// The csrf session of a client : a cookie jar and the token found on the landing page.
+#[derive(Default)]
+pub struct Session {
+
+    state:Mutex<SessionState>,
+    handshake_done:Condvar,
+
+}
+
+/// Marks the end of the handshake when dropped, even if it failed or its future was dropped, and wakes the callers waiting for it.
+struct HandshakeGuard<'a>(&'a Session);
+
+impl Drop for HandshakeGuard<'_> {
+
+    fn drop(&mut self) {
+
+        let mut state = self.0.state.lock().unwrap();
+
+        state.handshaking = false;
+
+        #[cfg(feature = "async")]
+        for waiter in state.async_waiters.drain(..) {
+            let _ = waiter.send(());
+        }
+
+        self.0.handshake_done.notify_all();
+
+    }
+
+}
+
+impl Session {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forgets the token and cookies if `rejected_token` is still the current token, the next request will load the landing page again.
+    /// A token already replaced by another caller is left untouched.
+    pub fn invalidate(&self, rejected_token:&str) {
+
+        let mut state = self.state.lock().unwrap();
+
+        if state.token.as_deref() == Some(rejected_token) {
+            state.token = None;
+            state.cookies.clear();
+        }
+
+    }
+
+    /// Returns true if `status` means that the server refused the csrf token.
+    pub fn is_rejection(status:u16) -> bool {
+        CSRF_REJECTION_STATUSES.contains(&status)
+    }
+
+    /// The request loading the landing page, from which the cookie and the token are taken.
+    pub fn get_handshake_request(config:&ClientConfig) -> GmailnatorRequest {
+
+        let mut request = GmailnatorRequest::from(HttpMethod::Get, &format!("{}/", config.base_url.trim_end_matches('/')));
+
+        request.set_header("User-Agent", &config.user_agent);
+
+        for (name, value) in &config.headers {
+            request.set_header(name, value);
+        }
+
+        request
+
+    }
+
+    /// Stores the cookies and the token of the landing page response.
+    pub fn complete_handshake(&self, response:&HttpResponse) -> Result<String, Error> {
+
+        if let Some(error) = get_blocking_error(response, false) { //The landing page may legitimately embed a captcha
+            return Err(error);
+        }
+
+        if response.is_error() {
+            return Err(get_status_error(response));
+        }
+
+        self.store_cookies(response);
+
+        let document = Html::parse_document(response.get_body());
+
+        let page_token = document.select(&CSRF_INPUT_SELECTOR)
+                                 .filter_map(|input| input.value().attr("value"))
+                                 .chain(document.select(&CSRF_META_SELECTOR).filter_map(|meta| meta.value().attr("content")))
+                                 .next()
+                                 .map(|token| token.to_string());
+
+        let mut state = self.state.lock().unwrap();
+
+        // The token field must match the cookie, the page is only used if it doesn't set one
+        let token = page_token.or_else(|| state.cookies.get(CSRF_COOKIE_NAME).cloned())
+                              .unwrap_or_default();
+
+        state.token = Some(token.clone());
+
+        Ok(token)
+
+    }
+
+    /// Loads the landing page through `transport` if no token is known yet and returns the token.
+    /// A single landing page is loaded at a time, the callers waiting for it then use its token.
+    pub fn ensure(&self, transport:&dyn HttpTransport, config:&ClientConfig) -> Result<String, Error> {
+
+        {
+
+            let mut state = self.state.lock().unwrap();
+
+            //Another caller may be loading the landing page, its token is used once it is done
+            while state.token.is_none() && state.handshaking {
+                state = self.handshake_done.wait(state).unwrap();
+            }
+
+            if let Some(token) = &state.token {
+                return Ok(token.clone());
+            }
+
+            state.handshaking = true;
+
+        }
+
+        let _handshake = HandshakeGuard(self);
+
+        let response = transport.send(&Session::get_handshake_request(config), "")?;
+
+        self.complete_handshake(&response)
+
+    }
+
+    /// Async counterpart of [`ensure`](#method.ensure), waiting for the handshake in progress without blocking the executor.
+    #[cfg(feature = "async")]
+    pub async fn ensure_async(&self, transport:&dyn AsyncHttpTransport, config:&ClientConfig) -> Result<String, Error> {
+
+        loop {
+
+            let handshake_done = {
+
+                let mut state = self.state.lock().unwrap();
+
+                if let Some(token) = &state.token {
+                    return Ok(token.clone());
+                }
+
+                if !state.handshaking {
+                    state.handshaking = true;
+                    break;
+                }
+
+                let (sender, receiver) = oneshot::channel();
+
+                state.async_waiters.push(sender);
+
+                receiver
+
+            };
+
+            let _ = handshake_done.await;
+
+        }
+
+        let _handshake = HandshakeGuard(self);
+
+        let response = transport.send(Session::get_handshake_request(config), String::new()).await?;
+
+        self.complete_handshake(&response)
+
+    }
+
+    /// Sets the `Cookie` header of `request` from the cookie jar.
+    /// The cookies already in the header are kept, unless the jar holds a cookie with the same name.
+    pub fn apply(&self, request:&mut GmailnatorRequest) {
+
+        let state = self.state.lock().unwrap();
+
+        let mut cookies = BTreeMap::new();
+
+        for pair in request.get_header("Cookie").unwrap_or_default().split(';') {
+
+            if let Some((name, value)) = pair.split_once('=') {
+                cookies.insert(name.trim().to_string(), value.trim().to_string());
+            }
+
+        }
+
+        cookies.extend(state.cookies.iter().map(|(name, value)| (name.clone(), value.clone())));
+
+        let mut cookie_header:Vec<String> = cookies.iter()
+                                                   .map(|(name, value)| format!("{}={}", name, value))
+                                                   .collect();
+
+        if !cookies.contains_key(CSRF_COOKIE_NAME) {
+            cookie_header.insert(0, format!("{}=", CSRF_COOKIE_NAME));
+        }
+
+        request.set_header("Cookie", &format!("{};", cookie_header.join("; ")));
+
+    }
+
+    /// Updates the cookie jar from the `Set-Cookie` headers of `response`.
+    pub fn store_cookies(&self, response:&HttpResponse) {
+
+        let mut state = self.state.lock().unwrap();
+
+        let set_cookies = response.get_headers()
+                                  .iter()
+                                  .filter(|(name, _)| name.eq_ignore_ascii_case("Set-Cookie"));
+
+        for (_, set_cookie) in set_cookies {
+
+            let pair = set_cookie.split(';').next().unwrap_or_default();
+
+            if let Some((name, value)) = pair.split_once('=') {
+
+                let (name, value) = (name.trim(), value.trim());
+
+                let is_expired = value.is_empty() || set_cookie.to_ascii_lowercase().contains("max-age=0");
+
+                if is_expired {
+                    state.cookies.remove(name);
+                } else {
+                    state.cookies.insert(name.to_string(), value.to_string());
+                }
+
+            }
+
+        }
+
+    }
+
+}
+
+/// Wraps the transport of a client to send the session cookies and store the ones it receives.
+pub struct SessionTransport {
+
+    pub transport:Arc<dyn HttpTransport>,
+    pub session:Arc<Session>,
+
+}
+
+impl HttpTransport for SessionTransport {
+
+    fn send(&self, request:&GmailnatorRequest, body:&str) -> Result<HttpResponse, Error> {
+
+        let mut request = request.clone();
+
+        self.session.apply(&mut request);
+
+        let response = self.transport.send(&request, body)?;
+
+        self.session.store_cookies(&response);
+
+        Ok(response)
+
+    }
+
+}
@@ -0,0 +1,585 @@
+use crate::mail::{GmailnatorInbox, MailMessage, Error};
+use crate::address::EmailAddress;
+
+use serde::{Serialize, Deserialize};
+
+use std::collections::{HashSet, HashMap};
+use std::sync::{Arc, Mutex, Condvar};
+use std::sync::mpsc::{self, Sender, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DEFAULT_CONCURRENCY:usize = 4;
+const DEFAULT_POLL_INTERVAL:Duration = Duration::from_secs(10);
+const DEFAULT_RATE_LIMIT:Duration = Duration::from_millis(200);
+
+/// Number of polls a message whose content keeps failing to be retrieved is tried in, before it is given up and marked as seen.
+const MAX_MESSAGE_ATTEMPTS:u32 = 3;
+
+/// The state of an [`InboxWatcher`](struct.InboxWatcher.html) that can be saved and resumed later : the inbox address and the ids of the messages already returned.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WatchSnapshot {
+
+    address:EmailAddress,
+    seen_ids:Vec<String>,
+
+}
+
+impl WatchSnapshot {
+
+    /// Gets the address of the watched inbox.
+    pub fn get_address(&self) -> &EmailAddress {
+        &self.address
+    }
+
+    /// Gets the ids of the messages already returned, sorted.
+    pub fn get_seen_ids(&self) -> &[String] {
+        &self.seen_ids
+    }
+
+}
+
+/// Polls an inbox and only returns the messages received since the previous poll.
+///
+/// The ids of the messages already returned are remembered, so each poll only downloads the mailbox list and the content of the new messages.
+/// The seen ids can be saved in a [`WatchSnapshot`](struct.WatchSnapshot.html) to resume watching after a restart.
+/// ```no_run
+/// use gmailnator::GmailnatorInbox;
+/// use std::{thread, time::Duration};
+///
+/// let inbox = GmailnatorInbox::new().unwrap();
+/// let mut watcher = inbox.watch();
+///
+/// loop {
+///
+///     for message in watcher.poll().unwrap() {
+///         println!("New message : {}", message.get_subject());
+///     }
+///
+///     let snapshot = serde_json::to_string(&watcher.get_snapshot()).unwrap();
+///     std::fs::write("watcher.json", snapshot).unwrap();
+///
+///     thread::sleep(Duration::from_secs(10));
+///
+/// }
+/// ```
+#[derive(Debug)]
+pub struct InboxWatcher {
+
+    inbox:GmailnatorInbox,
+    seen_ids:HashSet<String>,
+    failed_attempts:HashMap<String, u32>,   //Message id -> number of polls its content could not be retrieved in
+
+}
+
+impl InboxWatcher {
+
+    pub(crate) fn new(inbox:&GmailnatorInbox, seen_ids:HashSet<String>) -> Self {
+        Self {inbox:inbox.clone(), seen_ids, failed_attempts:HashMap::new()}
+    }
+
+    /// Returns the messages that were not returned by the previous polls, in the order of the mailbox list.
+    /// Only a failure to retrieve the mailbox list is returned, the messages whose content can't be retrieved are skipped, see [`try_poll`](#method.try_poll).
+    pub fn poll(&mut self) -> Result<Vec<MailMessage>, Error> {
+        Ok(self.try_poll()?.into_iter().filter_map(Result::ok).collect())
+    }
+
+    /// Like [`poll`](#method.poll), but also returns the error of each new message whose content could not be retrieved.
+    ///
+    /// A failed message is not marked as seen, so the next polls try it again. After failing in 3 polls for another reason than
+    /// a transport failure or a blocking page, it is given up and marked as seen. A blocking page ends the poll, the remaining messages waiting for the next one.
+    pub fn try_poll(&mut self) -> Result<Vec<Result<MailMessage, Error>>, Error> {
+
+        let mut results = Vec::new();
+        let mut polled = HashSet::new();
+
+        for summary in self.inbox.get_inbox_message_summaries()? {
+
+            if self.seen_ids.contains(&summary.id) || !polled.insert(summary.id.clone()) {
+                continue;
+            }
+
+            match GmailnatorInbox::get_message_by_id(self.inbox.get_client(), &self.inbox.temp_server, &summary) {
+
+                Ok(message) => {
+
+                    self.failed_attempts.remove(&summary.id);
+                    self.seen_ids.insert(summary.id);
+
+                    results.push(Ok(message));
+
+                },
+                Err(error) if error.is_blocking_error() => { //The client has to back off
+                    results.push(Err(error));
+                    break;
+                },
+                Err(error) => {
+
+                    if !error.is_transport_error() {
+
+                        let attempts = self.failed_attempts.entry(summary.id.clone()).or_insert(0);
+
+                        *attempts += 1;
+
+                        if *attempts >= MAX_MESSAGE_ATTEMPTS {
+                            self.failed_attempts.remove(&summary.id);
+                            self.seen_ids.insert(summary.id);
+                        }
+
+                    }
+
+                    results.push(Err(error));
+
+                },
+
+            }
+
+        }
+
+        Ok(results)
+
+    }
+
+    /// Marks the messages currently in the inbox as seen without downloading them, so that only the messages received afterwards are returned.
+    pub fn skip_existing(&mut self) -> Result<(), Error> {
+
+        let summaries = self.inbox.get_inbox_message_summaries()?;
+
+        self.seen_ids.extend(summaries.into_iter().map(|summary| summary.id));
+
+        Ok(())
+
+    }
+
+    /// Returns true if the message with the id `message_id` was already returned or skipped.
+    pub fn is_seen(&self, message_id:&str) -> bool {
+        self.seen_ids.contains(message_id)
+    }
+
+    /// Returns the number of messages already returned or skipped.
+    pub fn get_seen_count(&self) -> usize {
+        self.seen_ids.len()
+    }
+
+    /// Returns the watched inbox.
+    pub fn get_inbox(&self) -> &GmailnatorInbox {
+        &self.inbox
+    }
+
+    /// Saves the seen ids, see [`GmailnatorInbox::resume_watch`](struct.GmailnatorInbox.html#method.resume_watch).
+    pub fn get_snapshot(&self) -> WatchSnapshot {
+
+        let mut seen_ids:Vec<String> = self.seen_ids.iter().cloned().collect();
+
+        seen_ids.sort();
+
+        WatchSnapshot {address:self.inbox.get_email_address().clone(), seen_ids}
+
+    }
+
+}
+
+/// A watched inbox, its watcher is taken out while a worker polls it.
+struct WatchEntry {
+
+    watcher:Option<InboxWatcher>,
+    next_poll:Instant,
+
+}
+
+struct WatchState {
+
+    entries:HashMap<String, WatchEntry>,    //Inbox address -> entry
+    errors:HashMap<String, Error>,          //Inbox address -> error of its last failed poll
+    stopped:bool,
+
+}
+
+struct WatchShared {
+
+    state:Mutex<WatchState>,
+    changed:Condvar,
+    next_slot:Mutex<Instant>,   //Earliest time the next poll may start at
+
+    poll_interval:Duration,
+    rate_limit:Duration,
+
+}
+
+impl WatchShared {
+
+    /// Blocks until an inbox is due and takes its watcher, `None` once the multi watcher is stopped.
+    fn take_due(&self) -> Option<(String, InboxWatcher)> {
+
+        let mut state = self.state.lock().unwrap();
+
+        loop {
+
+            if state.stopped {
+                return None;
+            }
+
+            let now = Instant::now();
+
+            let due = state.entries.iter()
+                                   .filter(|(_, entry)| entry.watcher.is_some())
+                                   .min_by_key(|(_, entry)| entry.next_poll)
+                                   .map(|(address, entry)| (address.clone(), entry.next_poll));
+
+            state = match due {
+
+                Some((address, next_poll)) if next_poll <= now => {
+
+                    let watcher = state.entries.get_mut(&address).and_then(|entry| entry.watcher.take())?;
+
+                    return Some((address, watcher));
+
+                },
+                Some((_, next_poll)) => self.changed.wait_timeout(state, next_poll - now).unwrap().0,
+                None => self.changed.wait(state).unwrap(),
+
+            };
+
+        }
+
+    }
+
+    /// Waits for the shared rate limit to allow one more poll, returns false if the multi watcher is stopped in the meantime.
+    fn wait_for_slot(&self) -> bool {
+
+        let slot = {
+
+            let mut next_slot = self.next_slot.lock().unwrap();
+
+            let slot = (*next_slot).max(Instant::now());
+            *next_slot = slot + self.rate_limit;
+
+            slot
+
+        };
+
+        let mut state = self.state.lock().unwrap();
+
+        loop {
+
+            if state.stopped {
+                return false;
+            }
+
+            let now = Instant::now();
+
+            if now >= slot {
+                return true;
+            }
+
+            state = self.changed.wait_timeout(state, slot - now).unwrap().0;
+
+        }
+
+    }
+
+    /// Puts the watcher back after its poll and sends the new messages, unless the inbox was removed in the meantime.
+    fn finish(&self, address:String, watcher:InboxWatcher, result:Result<Vec<Result<MailMessage, Error>>, Error>, sender:&Sender<(String, MailMessage)>) {
+
+        let mut state = self.state.lock().unwrap();
+
+        let mut next_poll = Instant::now() + self.poll_interval;
+
+        let retry_after = match &result {
+            Ok(results) => results.iter().filter_map(|result| result.as_ref().err()).find_map(Error::get_retry_after),
+            Err(error) => error.get_retry_after(),
+        };
+
+        //A rate limited client has to back off, whatever the inbox
+        if let Some(retry_after) = retry_after {
+
+            let resume = Instant::now() + retry_after;
+
+            next_poll = next_poll.max(resume);
+
+            let mut next_slot = self.next_slot.lock().unwrap();
+            *next_slot = (*next_slot).max(resume);
+
+        }
+
+        match state.entries.get_mut(&address) {
+
+            Some(entry) if entry.watcher.is_none() => {
+                entry.watcher = Some(watcher);
+                entry.next_poll = next_poll;
+            },
+            _ => { return; } //Removed, or removed then added again, while being polled
+
+        }
+
+        let results = match result {
+
+            Ok(results) => {
+                state.errors.remove(&address);
+                results
+            },
+            Err(error) => {
+                state.errors.insert(address.clone(), error);
+                Vec::new()
+            },
+
+        };
+
+        for result in results {
+
+            match result {
+                Ok(message) => { let _ = sender.send((address.clone(), message)); },
+                Err(error) => { state.errors.insert(address.clone(), error); },
+            }
+
+        }
+
+        self.changed.notify_all();
+
+    }
+
+}
+
+fn run_worker(shared:Arc<WatchShared>, sender:Sender<(String, MailMessage)>) {
+
+    while let Some((address, mut watcher)) = shared.take_due() {
+
+        if !shared.wait_for_slot() {
+            return;
+        }
+
+        let result = watcher.try_poll();
+
+        shared.finish(address, watcher, result, &sender);
+
+    }
+
+}
+
+/// Watches many inboxes at once and merges their new messages in a single stream of `(address, message)` events.
+///
+/// The inboxes are polled by a fixed number of worker threads, each one every poll interval at most.
+/// Polls are spaced by the shared rate limit whatever the inbox, and a rate limited poll delays every inbox by the `Retry-After` delay.
+/// Inboxes can be added and removed while the messages are being received.
+/// ```no_run
+/// use gmailnator::{GmailnatorClient, MultiInboxWatcher};
+/// use std::time::Duration;
+///
+/// let client = GmailnatorClient::default();
+///
+/// let watcher = MultiInboxWatcher::builder()
+///                                 .concurrency(8)
+///                                 .poll_interval(Duration::from_secs(30))
+///                                 .rate_limit(Duration::from_millis(100))
+///                                 .build()
+///                                 .unwrap();
+///
+/// for inbox in client.new_bulk(100).unwrap() {
+///     watcher.add(&inbox);
+/// }
+///
+/// for (address, message) in &watcher {
+///     println!("{} received {}", address, message.get_subject());
+/// }
+/// ```
+pub struct MultiInboxWatcher {
+
+    shared:Arc<WatchShared>,
+    receiver:Receiver<(String, MailMessage)>,
+
+}
+
+impl MultiInboxWatcher {
+
+    /// Returns a builder to configure a new multi watcher.
+    pub fn builder() -> MultiInboxWatcherBuilder {
+        MultiInboxWatcherBuilder::new()
+    }
+
+    /// Starts watching `inbox`, the messages it already holds are returned by its first poll.
+    /// Returns false if the inbox was already watched.
+    pub fn add(&self, inbox:&GmailnatorInbox) -> bool {
+        self.add_watcher(inbox.watch())
+    }
+
+    /// Starts watching the inbox of `watcher`, whose seen messages are not returned again.
+    /// Use a watcher that [skipped](struct.InboxWatcher.html#method.skip_existing) the existing messages to only get the ones received from now on.
+    /// Returns false if the inbox was already watched.
+    pub fn add_watcher(&self, watcher:InboxWatcher) -> bool {
+
+        let mut state = self.shared.state.lock().unwrap();
+
+        let address = watcher.get_inbox().get_address().to_string();
+
+        if state.entries.contains_key(&address) {
+            return false;
+        }
+
+        state.entries.insert(address, WatchEntry {watcher:Some(watcher), next_poll:Instant::now()});
+
+        self.shared.changed.notify_all();
+
+        true
+
+    }
+
+    /// Stops watching the inbox of `address`, returns false if it was not watched.
+    /// Messages of a poll that is running when the inbox is removed are not returned.
+    pub fn remove(&self, address:&str) -> bool {
+
+        let mut state = self.shared.state.lock().unwrap();
+
+        state.errors.remove(address);
+
+        state.entries.remove(address).is_some()
+
+    }
+
+    /// Returns the addresses of the watched inboxes.
+    pub fn get_addresses(&self) -> Vec<String> {
+        self.shared.state.lock().unwrap().entries.keys().cloned().collect()
+    }
+
+    /// Returns the number of watched inboxes.
+    pub fn len(&self) -> usize {
+        self.shared.state.lock().unwrap().entries.len()
+    }
+
+    /// Returns true if no inbox is watched.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the errors of the inboxes whose last poll failed, or could not retrieve one of the new messages, and clears them.
+    /// A failed poll is tried again after the poll interval, see [`InboxWatcher::try_poll`](struct.InboxWatcher.html#method.try_poll) for the failed messages.
+    pub fn take_errors(&self) -> Vec<(String, Error)> {
+        self.shared.state.lock().unwrap().errors.drain().collect()
+    }
+
+    /// Blocks until a new message is received.
+    pub fn recv(&self) -> Option<(String, MailMessage)> {
+        self.receiver.recv().ok()
+    }
+
+    /// Blocks until a new message is received, or returns `None` after `timeout`.
+    pub fn recv_timeout(&self, timeout:Duration) -> Option<(String, MailMessage)> {
+
+        match self.receiver.recv_timeout(timeout) {
+
+            Ok(event) => Some(event),
+            Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => None,
+
+        }
+
+    }
+
+    /// Returns a message already received, if any, without blocking.
+    pub fn try_recv(&self) -> Option<(String, MailMessage)> {
+        self.receiver.try_recv().ok()
+    }
+
+}
+
+/// Blocks on every call to `next()` until a new message is received, the iteration never ends by itself.
+impl Iterator for &MultiInboxWatcher {
+
+    type Item = (String, MailMessage);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.recv()
+    }
+
+}
+
+/// Stops the workers without waiting for them : a worker waiting for its turn stops at once, and a worker polling an inbox stops once its request is over.
+impl Drop for MultiInboxWatcher {
+
+    fn drop(&mut self) {
+
+        self.shared.state.lock().unwrap().stopped = true;
+        self.shared.changed.notify_all();
+
+    }
+
+}
+
+/// Builds a [`MultiInboxWatcher`](struct.MultiInboxWatcher.html).
+pub struct MultiInboxWatcherBuilder {
+
+    concurrency:usize,
+    poll_interval:Duration,
+    rate_limit:Duration,
+
+}
+
+impl MultiInboxWatcherBuilder {
+
+    /// Creates a builder with the default settings : 4 workers polling each inbox every 10 seconds, with 200ms between two polls.
+    pub fn new() -> Self {
+
+        Self {
+            concurrency:DEFAULT_CONCURRENCY,
+            poll_interval:DEFAULT_POLL_INTERVAL,
+            rate_limit:DEFAULT_RATE_LIMIT,
+        }
+
+    }
+
+    /// Sets how many inboxes can be polled at the same time.
+    pub fn concurrency(mut self, concurrency:usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Sets the delay between the end of a poll of an inbox and the next poll of the same inbox.
+    pub fn poll_interval(mut self, poll_interval:Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Sets the minimum delay between the start of two polls, whatever their inboxes.
+    pub fn rate_limit(mut self, rate_limit:Duration) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
+
+    /// Starts the workers, returns an `Error::ClientConfigError` if the concurrency is 0.
+    pub fn build(self) -> Result<MultiInboxWatcher, Error> {
+
+        if self.concurrency == 0 {
+            return Err(Error::ClientConfigError("concurrency must be at least 1".to_string()));
+        }
+
+        let shared = Arc::new(WatchShared {
+            state:Mutex::new(WatchState {entries:HashMap::new(), errors:HashMap::new(), stopped:false}),
+            changed:Condvar::new(),
+            next_slot:Mutex::new(Instant::now()),
+            poll_interval:self.poll_interval,
+            rate_limit:self.rate_limit,
+        });
+
+        let (sender, receiver) = mpsc::channel();
+
+        //The workers are detached, they end by themselves once the multi watcher is dropped
+        for _ in 0..self.concurrency {
+
+            let shared = shared.clone();
+            let sender = sender.clone();
+
+            thread::spawn(move || run_worker(shared, sender));
+
+        }
+
+        Ok(MultiInboxWatcher {shared, receiver})
+
+    }
+
+}
+
+impl Default for MultiInboxWatcherBuilder {
+
+    fn default() -> Self {
+        Self::new()
+    }
+
+}